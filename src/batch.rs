@@ -0,0 +1,115 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use crate::cli::PathStyle;
+
+/// Conservative limit for the size of all arguments and the environment together.
+///
+/// Linux allows a quarter of the stack size (usually 2 MiB) but only 128 KiB per argument, macOS allows 1 MiB.
+#[cfg(unix)]
+const ARG_MAX: usize = 128 * 1024;
+/// The whole command line is limited to 32767 UTF-16 units.
+#[cfg(not(unix))]
+const ARG_MAX: usize = 32 * 1024 - 1024;
+
+/// The working directory of the command and the found `paths` as arguments relative to it.
+///
+/// Styles which are only meant for display, like `--path-style=short`, are relative to the `base_dir`.
+pub fn arguments(style: PathStyle, base_dir: &Path, paths: &[PathBuf]) -> (PathBuf, Vec<OsString>) {
+    let working_dir = match style {
+        PathStyle::WorkingDir => PathBuf::from("."),
+        PathStyle::BaseDir | PathStyle::Canonical | PathStyle::Dirname | PathStyle::Short => {
+            base_dir.to_path_buf()
+        }
+    };
+    let arguments = paths
+        .iter()
+        .map(|path| match style {
+            PathStyle::Canonical => path.canonicalize().unwrap_or_else(|_| path.clone()),
+            PathStyle::WorkingDir => path.clone(),
+            PathStyle::BaseDir | PathStyle::Dirname | PathStyle::Short => {
+                match path.strip_prefix(base_dir) {
+                    Ok(relative) if relative.as_os_str().is_empty() => PathBuf::from("."),
+                    Ok(relative) => relative.to_path_buf(),
+                    Err(_) => path.clone(),
+                }
+            }
+        })
+        .map(PathBuf::into_os_string)
+        .collect();
+    (working_dir, arguments)
+}
+
+/// Split the `arguments` into batches which fit the limit of the OS together with the `command` and the environment.
+///
+/// Every batch has at least one argument even when it alone is too big.
+pub fn split(command: &[OsString], arguments: Vec<OsString>) -> Vec<Vec<OsString>> {
+    let environment = std::env::vars_os()
+        .map(|(key, value)| size(&key) + size(&value))
+        .sum::<usize>();
+    let fixed = command.iter().map(|arg| size(arg)).sum::<usize>() + environment;
+    split_with_limit(ARG_MAX.saturating_sub(fixed), arguments)
+}
+
+fn split_with_limit(limit: usize, arguments: Vec<OsString>) -> Vec<Vec<OsString>> {
+    let mut batches = Vec::new();
+    let mut batch = Vec::new();
+    let mut batch_size = 0;
+    for argument in arguments {
+        let size = size(&argument);
+        if !batch.is_empty() && batch_size + size > limit {
+            batches.push(std::mem::take(&mut batch));
+            batch_size = 0;
+        }
+        batch_size += size;
+        batch.push(argument);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
+}
+
+/// Size of an argument including its terminating NUL and pointer.
+fn size(arg: &OsStr) -> usize {
+    arg.len() + 1 + std::mem::size_of::<usize>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(amount: usize) -> Vec<OsString> {
+        (0..amount).map(|i| format!("{i:07}").into()).collect()
+    }
+
+    #[test]
+    fn fits_in_one() {
+        let batches = split_with_limit(1000, args(10));
+        assert_eq!(batches, [args(10)]);
+    }
+
+    #[test]
+    fn splits() {
+        // Every argument takes 7 + 1 + 8 = 16 bytes on 64 bit
+        let limit = 4 * size(OsStr::new("0000000"));
+        let batches = split_with_limit(limit, args(10));
+        let lengths = batches.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(lengths, [4, 4, 2]);
+        assert_eq!(batches.concat(), args(10));
+    }
+
+    #[test]
+    fn too_big_argument_still_runs() {
+        let batches = split_with_limit(1, args(2));
+        assert_eq!(batches.len(), 2);
+    }
+
+    #[test]
+    fn relative_to_base_dir() {
+        let paths = [PathBuf::from("base/a"), PathBuf::from("base")];
+        let (working_dir, arguments) = arguments(PathStyle::BaseDir, Path::new("base"), &paths);
+        assert_eq!(working_dir, Path::new("base"));
+        assert_eq!(arguments, ["a", "."]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// `$XDG_CACHE_HOME/project-below` or `~/.cache/project-below`
+pub fn dir() -> Option<PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache_dir.join(env!("CARGO_PKG_NAME")))
+}
+
+/// `<cache dir>/<prefix>-<hash of the parts>`
+///
+/// The hash is stable across builds so the files are still found after an update.
+pub fn file(prefix: &str, parts: &[&OsStr]) -> Option<PathBuf> {
+    Some(dir()?.join(format!("{prefix}-{:016x}", hash(parts))))
+}
+
+fn hash(parts: &[&OsStr]) -> u64 {
+    let mut hash = Fnv1a::default();
+    for part in parts {
+        hash.write(&bytes(part));
+        // Separate the parts so ["ab", "c"] and ["a", "bc"] differ
+        hash.write(&[0]);
+    }
+    hash.0
+}
+
+/// Write to a temporary file and rename it to not leave a half written file behind for parallel or interrupted runs.
+pub fn write(file: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = file.with_extension(format!("{}.tmp", std::process::id()));
+    std::fs::write(&temp, content)?;
+    std::fs::rename(temp, file)
+}
+
+/// Remove the files with the `prefix` which were not written for longer than `max_age`.
+///
+/// Failures are ignored, another run can still remove them.
+pub fn prune(prefix: &str, max_age: Duration) {
+    let Some(entries) = dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return;
+    };
+    let prefix = format!("{prefix}-");
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with(&prefix) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age > max_age) {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Content of a cache file as records which each end with a NUL, as it can not be part of a path.
+///
+/// The first record is the version of the format.
+/// Increase the version when the format changes, files of other versions are ignored then.
+pub struct Records(String);
+
+impl Records {
+    pub fn new(version: &str) -> Self {
+        let mut records = Self(String::new());
+        records.push(version);
+        records
+    }
+
+    /// NUL within the `record` is dropped.
+    pub fn push(&mut self, record: &str) {
+        self.0 += &record.replace('\0', "");
+        self.0.push('\0');
+    }
+
+    /// A record of the char of the `tag` followed by the `value`.
+    pub fn push_tagged<T: Tag>(&mut self, tag: T, value: &str) {
+        self.0.push(tag.as_char());
+        self.push(value);
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The records of the `content` after its `version` or [`None`] for other versions.
+pub fn records<'a>(content: &'a str, version: &str) -> Option<std::str::Split<'a, char>> {
+    let mut records = content.strip_suffix('\0')?.split('\0');
+    (records.next()? == version).then_some(records)
+}
+
+/// Split a record written by [`Records::push_tagged`] into its tag and value.
+pub fn tagged<T: Tag>(record: &str) -> Option<(T, &str)> {
+    let mut chars = record.chars();
+    let tag = T::from_char(chars.next()?)?;
+    Some((tag, chars.as_str()))
+}
+
+/// Values which are stored as a single char in front of a record.
+pub trait Tag: Copy + PartialEq + 'static {
+    const CHARS: &'static [(Self, char)];
+
+    fn as_char(self) -> char {
+        Self::CHARS
+            .iter()
+            .find(|(tag, _)| *tag == self)
+            .map(|(_, char)| *char)
+            .expect("every tag should have a char")
+    }
+
+    fn from_char(char: char) -> Option<Self> {
+        Self::CHARS
+            .iter()
+            .find(|(_, known)| *known == char)
+            .map(|(tag, _)| *tag)
+    }
+}
+
+#[cfg(unix)]
+fn bytes(part: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    part.as_bytes().to_vec()
+}
+
+#[cfg(windows)]
+fn bytes(part: &OsStr) -> Vec<u8> {
+    use std::os::windows::ffi::OsStrExt;
+    part.encode_wide().flat_map(u16::to_le_bytes).collect()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn bytes(part: &OsStr) -> Vec<u8> {
+    part.to_string_lossy().into_owned().into_bytes()
+}
+
+/// 64 bit FNV-1a which, unlike the `DefaultHasher`, is the same across Rust versions.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[test]
+fn fnv1a_known_values() {
+    let hash = |input: &[u8]| {
+        let mut hash = Fnv1a::default();
+        hash.write(input);
+        hash.0
+    };
+    assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+    assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
+}
+
+#[test]
+fn hash_separates_parts() {
+    let first = hash(&[OsStr::new("ab"), OsStr::new("c")]);
+    let second = hash(&[OsStr::new("a"), OsStr::new("bc")]);
+    assert_ne!(first, second);
+}
+
+#[test]
+fn records_roundtrip() {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Kind {
+        A,
+        B,
+    }
+    impl Tag for Kind {
+        const CHARS: &'static [(Self, char)] = &[(Self::A, 'a'), (Self::B, 'b')];
+    }
+
+    let mut written = Records::new("test-1");
+    written.push("some\0thing");
+    written.push_tagged(Kind::B, "value");
+    let mut parsed = records(written.as_str(), "test-1").unwrap();
+    assert_eq!(parsed.next(), Some("something"));
+    assert_eq!(tagged(parsed.next().unwrap()), Some((Kind::B, "value")));
+    assert_eq!(parsed.next(), None);
+    assert_eq!(tagged::<Kind>("cvalue"), None);
+
+    assert!(records(written.as_str(), "test-2").is_none());
+    assert!(records("test-1", "test-1").is_none());
+}
@@ -1,37 +1,75 @@
 use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::path::{Component, Path, PathBuf};
 
-pub fn check_dir_is_project(patterns: &[Pattern], dir: &Path) -> bool {
+use crate::index::{EntryKind, Index};
+
+/// Directories below `dir` which could not be read are added to `errors`.
+/// `dir` itself is not added as the walker reports it when it tries to enter it.
+pub fn check_dir_is_project(
+    patterns: &[Pattern],
+    dir: &Path,
+    index: &Index,
+    errors: &mut Vec<(PathBuf, std::io::Error)>,
+) -> bool {
+    project_matches(patterns, dir, index, errors).is_some()
+}
+
+/// Same as [`check_dir_is_project`] but returns the paths which matched the patterns.
+///
+/// Only the first matches are found as patterns are no longer searched for once they matched.
+pub fn project_matches(
+    patterns: &[Pattern],
+    dir: &Path,
+    index: &Index,
+    errors: &mut Vec<(PathBuf, std::io::Error)>,
+) -> Option<Vec<PathBuf>> {
     let mut state = patterns
         .iter()
         .map(|pattern| pattern.unique_identifier)
         .collect();
-    drop(recursive(&mut state, dir, patterns));
+    let mut matched = Vec::new();
+    drop(recursive(
+        &mut state,
+        &mut matched,
+        dir,
+        patterns,
+        index,
+        errors,
+    ));
 
     // When a pattern is matched successfully it will be removed from the state.
     // If it is still there, it never matched.
-    state.is_empty()
+    state.is_empty().then_some(matched)
 }
 
 /// `state` contains the `unique_identifier` that still need to be matched to accept the given path as a project
-fn recursive(state: &mut HashSet<usize>, dir: &Path, patterns: &[Pattern]) -> std::io::Result<()> {
-    let entries = dir
-        .read_dir()?
-        .filter_map(Result::ok)
-        .map(|entry| entry.path());
+fn recursive(
+    state: &mut HashSet<usize>,
+    matched: &mut Vec<PathBuf>,
+    dir: &Path,
+    patterns: &[Pattern],
+    index: &Index,
+    errors: &mut Vec<(PathBuf, std::io::Error)>,
+) -> std::io::Result<()> {
+    let entries = index.read_dir(dir)?;
     let mut dirs = Vec::new();
 
-    for path in entries {
+    for entry in entries.iter() {
         let matched_patterns = patterns
             .iter()
-            .filter(|pattern| pattern.matches(&path))
-            .map(|pattern| &pattern.unique_identifier);
+            .filter(|pattern| pattern.matches(&entry.name, entry.kind))
+            .map(|pattern| &pattern.unique_identifier)
+            .collect::<Vec<_>>();
+        if !matched_patterns.is_empty() {
+            matched.push(dir.join(&entry.name));
+        }
         for to_be_removed in matched_patterns {
             state.remove(to_be_removed);
         }
 
-        if path.is_dir() {
-            dirs.push(path);
+        if entry.kind == EntryKind::Directory {
+            dirs.push(dir.join(&entry.name));
         }
     }
 
@@ -48,7 +86,10 @@ fn recursive(state: &mut HashSet<usize>, dir: &Path, patterns: &[Pattern]) -> st
                 .collect::<Vec<_>>();
 
             if !relevant_patterns.is_empty() {
-                drop(recursive(state, &dir, &relevant_patterns));
+                if let Err(err) = recursive(state, matched, &dir, &relevant_patterns, index, errors)
+                {
+                    errors.push((dir, err));
+                }
             }
         }
     }
@@ -155,18 +196,14 @@ impl Pattern {
         }
     }
 
-    fn matches(&self, path: &Path) -> bool {
+    fn matches(&self, name: &OsStr, kind: EntryKind) -> bool {
         match self.position {
             Position::Anywhere | Position::Here => {
                 let kind_matches = match self.kind {
-                    Kind::File => path.is_file(),
-                    Kind::Directory => path.is_dir(),
+                    Kind::File => kind == EntryKind::File,
+                    Kind::Directory => kind == EntryKind::Directory,
                 };
-                if !kind_matches {
-                    return false;
-                }
-                path.file_name()
-                    .is_some_and(|name| self.target.is_match(name))
+                kind_matches && self.target.is_match(name)
             }
             Position::Below { .. } => false,
         }
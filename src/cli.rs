@@ -1,11 +1,15 @@
 use std::ffi::OsString;
 use std::num::NonZeroUsize;
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::{Parser, ValueEnum, ValueHint};
 
 #[derive(Debug, Parser)]
 #[command(about, version)]
+// Arguments of the commands which also apply to the `--filter-exec` command
+#[command(group = clap::ArgGroup::new("commands").args(["command", "filter_exec"]).multiple(true))]
 #[allow(clippy::partial_pub_fields, clippy::struct_excessive_bools)]
 pub struct Cli {
     /// Base directory from where the search starts
@@ -45,6 +49,86 @@ pub struct Cli {
     #[arg(long)]
     pub hidden: bool,
 
+    /// Keep an index of the directory entries used for the `--directory` and `--file` patterns.
+    ///
+    /// The index is stored in the cache directory (`$XDG_CACHE_HOME` or `~/.cache`) and contains the file names of the checked directories.
+    /// Checking the patterns skips reading directories which were not modified since the last run.
+    /// The directory walk itself still reads every directory, so this only helps with patterns like `**/*.rs` on slow filesystems.
+    /// Entries used by other patterns are kept. After a complete walk directories which no longer exist are removed from the index.
+    ///
+    /// The index is opt-in as it writes to the cache directory on every run.
+    #[arg(long, overrides_with = "no_cache")]
+    pub cache: bool,
+
+    /// Don't use the index of directory entries, for example to override `--cache` of an alias.
+    #[arg(long, overrides_with = "cache")]
+    pub no_cache: bool,
+
+    /// Ignore the existing index of directory entries and create a new one.
+    ///
+    /// The index is kept valid by the modification time of each directory.
+    #[arg(long, requires = "cache")]
+    pub refresh: bool,
+
+    /// Don't print warnings and errors of the directory walk.
+    ///
+    /// The exit status still fails when directories couldn't be read.
+    #[arg(long)]
+    pub no_warnings: bool,
+
+    /// Exit with an error status on warnings of the directory walk too.
+    ///
+    /// Without this only directories which couldn't be read result in an error status.
+    /// Warnings are for example ignore files which couldn't be parsed.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Amount of threads to walk the directories with.
+    ///
+    /// Defaults to the available CPU cores but at most 64.
+    /// Fewer threads might work better on slow or networked filesystems.
+    #[arg(long, value_name = "NUM")]
+    pub walk_threads: Option<NonZeroUsize>,
+
+    /// Stop the walk once this amount of projects were found.
+    ///
+    /// With `--sort` the walk continues to find all projects and only the first ones after sorting are used.
+    #[arg(long, value_name = "NUM", conflicts_with = "watch")]
+    pub max_results: Option<NonZeroUsize>,
+
+    /// Only keep the projects in which this command succeeds.
+    ///
+    /// The command is run via `--shell` or `sh` in every found project in parallel and its output is discarded.
+    /// It supports the same placeholders as the command, for example `--filter-exec 'git diff --quiet'`.
+    /// It still runs with `--dry-run` to decide which projects are shown.
+    #[arg(long, value_name = "CMD", conflicts_with = "watch")]
+    pub filter_exec: Option<OsString>,
+
+    /// Keep the projects in which the `--filter-exec` command fails instead.
+    #[arg(long, requires = "filter_exec")]
+    pub invert: bool,
+
+    /// Sort the found projects before listing them or executing the command in them.
+    ///
+    /// The results of the parallel directory walk arrive in a random order.
+    /// Sorting waits for the walk to finish and results in a reproducible order.
+    #[arg(long, value_enum, value_name = "KEY")]
+    pub sort: Option<SortBy>,
+
+    /// Reverse the order of `--sort`.
+    #[arg(long, requires = "sort")]
+    pub reverse: bool,
+
+    /// Keep running after the initial walk and react to changes below the `--base-dir`.
+    ///
+    /// Without a command newly created projects are listed.
+    /// With a command it is run in new projects and again in projects where files changed.
+    /// Changes to ignored and hidden files are not considered.
+    /// Changes to a project while its command is waiting or running, or right after it finished, do not run it again.
+    /// This way commands writing into their own project do not trigger themselves.
+    #[arg(long)]
+    pub watch: bool,
+
     /// List all the directories instead of executing a command.
     ///
     /// This can be helpful for piping into other tools like `fzf`.
@@ -123,6 +207,198 @@ pub struct Cli {
     )]
     pub result: CommandResult,
 
+    /// Run each command in its own pseudo-terminal to keep colours and interactive output (Unix only).
+    ///
+    /// Only useful with `--output=line-prefix` or `--output=collect` where the output would be read via pipes otherwise.
+    /// Both stdout and stderr of the command end up in the pseudo-terminal and are printed to stdout.
+    /// Its size is the one of the current terminal minus the line prefix.
+    #[arg(long, requires = "command", help_heading = "Command Options")]
+    pub pty: bool,
+
+    /// Terminate a command when it takes longer than this.
+    ///
+    /// The command and all its child processes get SIGTERM and SIGKILL after `--kill-after`.
+    /// With `-j1` a command reading from the terminal with `--stdin=inherit` gets the terminal for its own process group.
+    /// Commands running in parallel can not share the terminal that way, they stay in the process group of project-below and only the command itself gets the signals.
+    /// Durations are given like `30s`, `5m`, `1h` or `500ms`. Seconds are assumed without a unit.
+    /// This also applies to the `--filter-exec` command.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        requires = "commands",
+        help_heading = "Command Options"
+    )]
+    pub timeout: Option<Duration>,
+
+    /// Kill a command when it is still running this long after it was terminated by `--timeout` or `--fail-fast=kill`.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "10s",
+        requires = "commands",
+        help_heading = "Command Options"
+    )]
+    pub kill_after: Duration,
+
+    /// Run a failed command again up to this many times.
+    ///
+    /// Helpful for flaky network commands like `git fetch`.
+    /// The result shows the amount of attempts and the time they took including the delays.
+    #[arg(
+        long,
+        value_name = "NUM",
+        default_value_t = 0,
+        requires = "command",
+        help_heading = "Command Options"
+    )]
+    pub retry: u32,
+
+    /// Delay before the first retry. It doubles for each further retry.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        value_parser = parse_duration,
+        default_value = "1s",
+        requires = "retry",
+        help_heading = "Command Options"
+    )]
+    pub retry_delay: Duration,
+
+    /// Only retry commands failing with one of these exit codes.
+    ///
+    /// Without this all failures are retried, including commands killed by a signal or `--timeout`.
+    #[arg(
+        long,
+        value_name = "CODES",
+        value_delimiter = ',',
+        requires = "retry",
+        help_heading = "Command Options"
+    )]
+    pub retry_on: Vec<i32>,
+
+    /// Stop as soon as a command failed.
+    ///
+    /// No further commands are started and the directory walk stops.
+    /// With `--fail-fast=kill` the commands which are still running are terminated too and killed after `--kill-after`.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "RUNNING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "wait",
+        requires = "command",
+        conflicts_with = "watch",
+        help_heading = "Command Options"
+    )]
+    pub fail_fast: Option<FailFast>,
+
+    /// How the exit codes of all commands are combined to the exit code of project-below.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = ExitCodeMode::Any,
+        requires = "command",
+        help_heading = "Command Options"
+    )]
+    pub exit_code: ExitCodeMode,
+
+    /// Where the commands read their input from.
+    ///
+    /// `none` closes the input and `inherit` shares the input of project-below with all commands.
+    /// `file:PATH` reads the file once and passes its content to every command, `file:-` does this with piped input.
+    /// With `tty` commands get the terminal one at a time when they read from it while the others keep running in the background (Unix only).
+    ///
+    /// Defaults to `inherit` with `--output=inherit` and `none` otherwise or with `--confirm`, which does not allow `inherit` and `tty`.
+    #[arg(
+        long,
+        value_name = "none|inherit|tty|file:PATH",
+        value_parser = parse_stdin,
+        requires = "command",
+        help_heading = "Command Options"
+    )]
+    pub stdin: Option<Stdin>,
+
+    /// Set an environment variable for the commands. Can be given multiple times.
+    ///
+    /// Takes precedence over the variables of `--env-file`.
+    #[arg(
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = parse_env,
+        requires = "command",
+        help_heading = "Command Options"
+    )]
+    pub env: Vec<(String, String)>,
+
+    /// Load environment variables for the command from this dotenv file within each project.
+    ///
+    /// Projects without this file run without it. Projects with a file which can not be read fail without running the command.
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_hint = ValueHint::FilePath,
+        requires = "command",
+        help_heading = "Command Options"
+    )]
+    pub env_file: Option<PathBuf>,
+
+    /// Ask before running the command in each project.
+    ///
+    /// The answer is read from the terminal, even when stdin is redirected.
+    /// Commands of already confirmed projects run while the next one is asked for, so they do not get the terminal as input.
+    #[arg(long, requires = "command", help_heading = "Command Options")]
+    pub confirm: bool,
+
+    /// Print what would be run in each project instead of running it.
+    ///
+    /// The command lines are shell-quoted and include the working directory and the additional environment variables.
+    /// Only the `--filter-exec` command still runs to select the projects.
+    #[arg(long, requires = "command", help_heading = "Command Options")]
+    pub dry_run: bool,
+
+    /// Skip the projects in which the command already succeeded in the previous run.
+    ///
+    /// Every run records which projects completed, failed or are still pending in the cache directory (`$XDG_CACHE_HOME` or `~/.cache`).
+    /// The state is kept per `--base-dir` and command so an interrupted run of the same command can be continued.
+    /// It is removed once the command completed in every project or when it was not used for 30 days.
+    #[arg(
+        long,
+        requires = "command",
+        conflicts_with_all = ["watch", "batch", "dry_run"],
+        help_heading = "Command Options"
+    )]
+    pub resume: bool,
+
+    /// Only run the command in the projects in which it failed in the previous run.
+    ///
+    /// Like `--resume` this uses the state recorded by the previous run of the same command below the `--base-dir`.
+    #[arg(
+        long,
+        requires = "command",
+        conflicts_with_all = ["watch", "batch", "dry_run", "resume"],
+        help_heading = "Command Options"
+    )]
+    pub rerun_failed: bool,
+
+    /// Run the command once with all found projects as arguments instead of once per project.
+    ///
+    /// The command runs in the `--base-dir` with the paths relative to it, or absolute with `--canonical`.
+    /// With `--path-style=working-dir` it runs in the current working directory instead.
+    /// When the arguments would exceed the limit of the OS the command is run multiple times.
+    /// Placeholders are not replaced.
+    #[arg(
+        long,
+        short = 'X',
+        requires = "command",
+        conflicts_with_all = ["watch", "threads", "env_file", "fail_fast", "confirm", "max_load", "max_cpu_pressure", "max_memory_pressure"],
+        help_heading = "Command Options"
+    )]
+    pub batch: bool,
+
     /// Execute multiple commands in parallel.
     ///
     /// A specific number of threads can be passed. Defaults to the available CPU cores.
@@ -139,6 +415,124 @@ pub struct Cli {
     #[allow(clippy::option_option)]
     threads: Option<Option<NonZeroUsize>>,
 
+    /// Run the command via a shell to allow for `&&`, pipes and redirections.
+    ///
+    /// The arguments of the command are joined with spaces and run via `SHELL -c`.
+    /// Without a value `$SHELL` is used or `sh` when it is not set.
+    /// Placeholders are quoted for the shell and the headers show the paths shell-quoted with their exact bytes.
+    #[arg(
+        long,
+        value_name = "SHELL",
+        value_hint = ValueHint::CommandName,
+        require_equals = true,
+        requires = "command",
+        help_heading = "Command Options"
+    )]
+    #[allow(clippy::option_option)]
+    shell: Option<Option<OsString>>,
+
+    /// Run the commands with this niceness to let other processes take precedence (Unix only).
+    ///
+    /// project-below itself gets this niceness and the commands inherit it.
+    /// This includes the directory walk and `--filter-exec` which run in project-below.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(i32).range(-20..=19),
+        allow_negative_numbers = true,
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub nice: Option<i32>,
+
+    /// Run the commands with this I/O scheduling class (Linux only).
+    ///
+    /// project-below itself gets this class and the commands inherit it.
+    /// It is set by running the `ionice` tool of util-linux which has to be installed.
+    #[arg(
+        long,
+        value_enum,
+        value_name = "CLASS",
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub ionice: Option<IoniceClass>,
+
+    /// Run the commands only on these CPUs like `0-3,6` (Linux only).
+    ///
+    /// project-below itself is pinned to them and the commands inherit it.
+    /// This includes the directory walk and `--filter-exec` which run in project-below.
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_parser = parse_cpu_range,
+        value_delimiter = ',',
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub cpu_affinity: Vec<RangeInclusive<usize>>,
+
+    /// Limit the address space of each command like `512M` or `4G` (Linux only).
+    ///
+    /// The command is run via `prlimit` of util-linux so the limit is in place before it runs.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub memory_limit: Option<u64>,
+
+    /// Limit the amount of open files of each command (Linux only).
+    ///
+    /// The command is run via `prlimit` of util-linux so the limit is in place before it runs.
+    #[arg(
+        long,
+        value_name = "NUM",
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub max_open_files: Option<u64>,
+
+    /// Only start another command while the 1 minute load average is below this (Linux only).
+    ///
+    /// Commands like `cargo build` are parallel themselves, so this is useful together with `-j` to not overload the machine.
+    /// At least one command is always running. The load average lags behind, so commands started at once are not prevented.
+    #[arg(
+        long,
+        value_name = "LOAD",
+        value_parser = parse_load,
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub max_load: Option<f64>,
+
+    /// Only start another command while the CPU pressure is below this percentage (Linux only).
+    ///
+    /// This is the share of the last 10 seconds in which some tasks waited for a CPU from `/proc/pressure/cpu`.
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = parse_percent,
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub max_cpu_pressure: Option<f64>,
+
+    /// Only start another command while the memory pressure is below this percentage (Linux only).
+    ///
+    /// This is the share of the last 10 seconds in which some tasks waited for memory from `/proc/pressure/memory`.
+    /// This keeps big fan-outs from swapping the machine.
+    #[arg(
+        long,
+        value_name = "PERCENT",
+        value_parser = parse_percent,
+        requires = "command",
+        help_heading = "Resource Controls"
+    )]
+    pub max_memory_pressure: Option<f64>,
+
     /// Shortcut for `--no-header --result=never`.
     #[arg(
         long,
@@ -171,6 +565,13 @@ pub struct Cli {
     quiet: bool,
 
     /// Command to be executed in each folder
+    ///
+    /// Arguments can contain placeholders which are replaced for each project:
+    /// `{path}` absolute path, `{name}` directory name, `{rel}` path relative to the `--base-dir`, `{parent}` absolute parent directory and `{match}` the files matching the patterns.
+    /// Double braces are literal braces, like `{{name}}` for a literal `{name}`.
+    ///
+    /// Multiple commands can be separated by `--then`. They run one after another in each project until one of them fails.
+    /// A literal `--then` argument is escaped as `\--then`.
     #[arg(
         value_hint = ValueHint::CommandWithArguments,
         trailing_var_arg = true,
@@ -193,6 +594,18 @@ pub enum PathStyle {
     WorkingDir,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortBy {
+    /// Alphabetically by the full path
+    Path,
+    /// Alphabetically by the name of the directory
+    Name,
+    /// Fewest path components first
+    Depth,
+    /// Least recently modified directory first
+    Mtime,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum CommandOutput {
     /// Inherit stdout and stderr. When used with multiple threads it can create hard to understand output as everything mixes up.
@@ -205,6 +618,44 @@ pub enum CommandOutput {
     Null,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExitCodeMode {
+    /// Fail when any command failed
+    Any,
+    /// Fail only when all commands failed
+    All,
+    /// Exit code of the last command which finished
+    Last,
+    /// Amount of failed commands (at most 255)
+    Count,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum IoniceClass {
+    /// Only get disk time when no other process needs it
+    Idle,
+    /// The default class
+    BestEffort,
+    /// Always get disk time first, needs root
+    Realtime,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum FailFast {
+    /// Let the commands which are already running finish
+    Wait,
+    /// Terminate the commands which are still running
+    Kill,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stdin {
+    None,
+    Inherit,
+    Tty,
+    File(PathBuf),
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum CommandResult {
     Always,
@@ -253,9 +704,59 @@ impl Cli {
             matches.output = CommandOutput::Null;
             matches.result = CommandResult::Never;
         }
+        if matches.confirm {
+            if let Err(err) = matches.confirm_stdin() {
+                err.exit();
+            }
+            matches.stdin.get_or_insert(Stdin::None);
+        }
+        if !matches.command.is_empty() && matches.steps().iter().any(Vec::is_empty) {
+            use clap::CommandFactory;
+            Self::command()
+                .error(
+                    clap::error::ErrorKind::InvalidValue,
+                    "every command separated by --then needs at least a program",
+                )
+                .exit();
+        }
         matches
     }
 
+    /// The prompt of `--confirm` reads the terminal while confirmed commands already run.
+    ///
+    /// A command with `--stdin=tty` would take over the terminal and stop project-below with SIGTTIN while it asks.
+    fn confirm_stdin(&self) -> Result<(), clap::Error> {
+        use clap::CommandFactory;
+        let stdin = match self.stdin {
+            Some(Stdin::Inherit) => "inherit",
+            Some(Stdin::Tty) => "tty",
+            _ => return Ok(()),
+        };
+        Err(Self::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!("--confirm can not share the terminal with --stdin={stdin}"),
+        ))
+    }
+
+    /// The commands separated by `--then`.
+    #[must_use]
+    pub fn steps(&self) -> Vec<Vec<OsString>> {
+        self.command
+            .split(|arg| arg == "--then")
+            .map(|step| step.iter().map(unescape_then).collect())
+            .collect()
+    }
+
+    #[must_use]
+    pub fn shell(&self) -> Option<OsString> {
+        self.shell.as_ref().map(|wanted| {
+            wanted
+                .clone()
+                .or_else(|| std::env::var_os("SHELL"))
+                .unwrap_or_else(|| "sh".into())
+        })
+    }
+
     #[must_use]
     pub fn threads(&self) -> NonZeroUsize {
         self.threads
@@ -264,8 +765,260 @@ impl Cli {
     }
 }
 
+/// Parse durations like `30s`, `5m`, `1h` or `500ms`. Without a unit seconds are assumed.
+fn parse_duration(raw: &str) -> Result<Duration, String> {
+    let split = raw
+        .find(|char: char| !char.is_ascii_digit() && char != '.')
+        .unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|err| format!("invalid number {number:?}: {err}"))?;
+    let factor = match unit.trim() {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        unit => return Err(format!("unknown unit {unit:?}, use ms, s, m or h")),
+    };
+    Duration::try_from_secs_f64(number * factor).map_err(|err| err.to_string())
+}
+
+fn parse_stdin(raw: &str) -> Result<Stdin, String> {
+    match raw {
+        "none" => Ok(Stdin::None),
+        "inherit" => Ok(Stdin::Inherit),
+        "tty" => Ok(Stdin::Tty),
+        _ => raw
+            .strip_prefix("file:")
+            .filter(|path| !path.is_empty())
+            .map(|path| Stdin::File(path.into()))
+            .ok_or_else(|| format!("expected none, inherit, tty or file:PATH, got {raw:?}")),
+    }
+}
+
+/// `\--then` is a literal `--then`. Only the first backslash is removed so `\\--then` is `\--then`.
+fn unescape_then(arg: &OsString) -> OsString {
+    match arg.to_str().and_then(|arg| arg.strip_prefix('\\')) {
+        Some(rest) if rest.trim_start_matches('\\') == "--then" => rest.into(),
+        _ => arg.clone(),
+    }
+}
+
+#[test]
+fn steps_work() {
+    let cli = Cli::try_parse_from([
+        "project-below",
+        "--file=Cargo.toml",
+        "echo",
+        r"\--then",
+        r"\\--then",
+        r"\x",
+        "--then",
+        "ls",
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.steps(),
+        [vec!["echo", "--then", r"\--then", r"\x"], vec!["ls"]]
+    );
+}
+
+#[test]
+fn confirm_conflicts_with_sharing_the_terminal() {
+    let parse = |stdin: &str| {
+        Cli::try_parse_from([
+            "project-below",
+            "--file=Cargo.toml",
+            "--confirm",
+            stdin,
+            "true",
+        ])
+        .unwrap()
+        .confirm_stdin()
+    };
+    assert!(parse("--stdin=none").is_ok());
+    assert!(parse("--stdin=file:-").is_ok());
+    assert_eq!(
+        parse("--stdin=inherit").unwrap_err().kind(),
+        clap::error::ErrorKind::ArgumentConflict
+    );
+    assert_eq!(
+        parse("--stdin=tty").unwrap_err().kind(),
+        clap::error::ErrorKind::ArgumentConflict
+    );
+}
+
+#[test]
+fn parse_stdin_works() {
+    assert_eq!(parse_stdin("none"), Ok(Stdin::None));
+    assert_eq!(parse_stdin("tty"), Ok(Stdin::Tty));
+    assert_eq!(parse_stdin("file:-"), Ok(Stdin::File("-".into())));
+    assert!(parse_stdin("file:").is_err());
+    assert!(parse_stdin("foo").is_err());
+}
+
+fn parse_cpu_range(raw: &str) -> Result<RangeInclusive<usize>, String> {
+    let parse = |cpu: &str| {
+        cpu.trim()
+            .parse::<usize>()
+            .map_err(|err| format!("invalid CPU {cpu:?}: {err}"))
+    };
+    let (start, end) = raw.split_once('-').unwrap_or((raw, raw));
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!("the range {raw:?} is empty"));
+    }
+    Ok(start..=end)
+}
+
+#[test]
+fn parse_cpu_range_works() {
+    assert_eq!(parse_cpu_range("3"), Ok(3..=3));
+    assert_eq!(parse_cpu_range("0-3"), Ok(0..=3));
+    assert!(parse_cpu_range("3-1").is_err());
+    assert!(parse_cpu_range("a").is_err());
+}
+
+/// Parse sizes like `512M` or `4G` with binary units. Without a unit bytes are assumed.
+fn parse_size(raw: &str) -> Result<u64, String> {
+    let split = raw
+        .find(|char: char| !char.is_ascii_digit())
+        .unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|err| format!("invalid number {number:?}: {err}"))?;
+    let exponent = match unit.trim().to_ascii_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(format!("unknown unit {unit:?}, use K, M, G or T")),
+    };
+    number
+        .checked_mul(1024_u64.pow(exponent))
+        .ok_or_else(|| "the size is too big".to_owned())
+}
+
+#[test]
+fn parse_size_works() {
+    assert_eq!(parse_size("42"), Ok(42));
+    assert_eq!(parse_size("2K"), Ok(2048));
+    assert_eq!(parse_size("512M"), Ok(512 * 1024 * 1024));
+    assert_eq!(parse_size("4gb"), Ok(4 * 1024 * 1024 * 1024));
+    assert!(parse_size("1X").is_err());
+    assert!(parse_size("G").is_err());
+}
+
+/// Parse load averages like `8` or `0.5`. They have to be greater than 0.
+fn parse_load(raw: &str) -> Result<f64, String> {
+    let load = raw
+        .parse::<f64>()
+        .map_err(|err| format!("invalid number {raw:?}: {err}"))?;
+    if load.is_finite() && load > 0.0 {
+        Ok(load)
+    } else {
+        Err("the load has to be a number greater than 0".to_owned())
+    }
+}
+
+#[test]
+fn parse_load_works() {
+    assert_eq!(parse_load("8"), Ok(8.0));
+    assert_eq!(parse_load("0.5"), Ok(0.5));
+    assert!(parse_load("0").is_err());
+    assert!(parse_load("-1").is_err());
+    assert!(parse_load("NaN").is_err());
+    assert!(parse_load("inf").is_err());
+}
+
+/// Parse percentages like `10` or `12.5%`.
+fn parse_percent(raw: &str) -> Result<f64, String> {
+    let number = raw.trim_end_matches('%');
+    let percent = number
+        .parse::<f64>()
+        .map_err(|err| format!("invalid number {number:?}: {err}"))?;
+    if (0.0..=100.0).contains(&percent) {
+        Ok(percent)
+    } else {
+        Err("the percentage has to be between 0 and 100".to_owned())
+    }
+}
+
+#[test]
+fn parse_percent_works() {
+    assert_eq!(parse_percent("10"), Ok(10.0));
+    assert_eq!(parse_percent("12.5%"), Ok(12.5));
+    assert!(parse_percent("101").is_err());
+    assert!(parse_percent("-1").is_err());
+    assert!(parse_percent("%").is_err());
+}
+
+fn parse_env(raw: &str) -> Result<(String, String), String> {
+    let (key, value) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {raw:?}"))?;
+    if key.is_empty() {
+        return Err("the key must not be empty".to_owned());
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+#[test]
+fn parse_env_works() {
+    assert_eq!(
+        parse_env("FOO=bar=baz"),
+        Ok(("FOO".to_owned(), "bar=baz".to_owned()))
+    );
+    assert_eq!(parse_env("FOO="), Ok(("FOO".to_owned(), String::new())));
+    assert!(parse_env("FOO").is_err());
+    assert!(parse_env("=bar").is_err());
+}
+
+#[test]
+fn parse_duration_works() {
+    assert_eq!(parse_duration("42"), Ok(Duration::from_secs(42)));
+    assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+    assert_eq!(parse_duration("1.5m"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(2 * 60 * 60)));
+    assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+    assert!(parse_duration("5d").is_err());
+    assert!(parse_duration("s").is_err());
+}
+
 #[test]
 fn verify() {
     use clap::CommandFactory;
     Cli::command().debug_assert();
 }
+
+#[test]
+fn resource_controls() {
+    let cli = Cli::try_parse_from([
+        "project-below",
+        "--file=Cargo.toml",
+        "--nice=-5",
+        "--ionice=idle",
+        "--cpu-affinity=0-1,3",
+        "--memory-limit=512M",
+        "--max-open-files=64",
+        "true",
+    ])
+    .unwrap();
+    assert_eq!(cli.nice, Some(-5));
+    assert!(matches!(cli.ionice, Some(IoniceClass::Idle)));
+    assert_eq!(cli.cpu_affinity, [0..=1, 3..=3]);
+    assert_eq!(cli.memory_limit, Some(512 * 1024 * 1024));
+    assert_eq!(cli.max_open_files, Some(64));
+
+    let parse =
+        |arg: &str| Cli::try_parse_from(["project-below", "--file=Cargo.toml", arg, "true"]);
+    assert!(parse("--nice=20").is_err());
+    assert!(parse("--ionice=fast").is_err());
+    assert!(parse("--cpu-affinity=3-1").is_err());
+    assert!(parse("--memory-limit=1X").is_err());
+    // Without a command there is nothing to limit
+    assert!(Cli::try_parse_from(["project-below", "--file=Cargo.toml", "--nice=5"]).is_err());
+}
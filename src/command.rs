@@ -1,99 +1,700 @@
-use std::ffi::OsString;
-use std::io::Write;
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io::{Read, Write};
 use std::path::Path;
-use std::process::{Command as OsCommand, ExitStatus, Output, Stdio};
+use std::process::{Child, Command as OsCommand, ExitStatus, Output, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread::Scope;
 use std::time::{Duration, Instant};
 
+use crossbeam_channel::{bounded, RecvTimeoutError};
+
 use crate::byte_lines::ReadByteLines;
+use crate::cli::{self, CommandOutput};
+use crate::resources::Limits;
+
+/// Set for commands sharing the terminal so they do not wait in a pager.
+const INHERIT_ENV: (&str, &str) = ("PAGER", "cat");
+
+#[derive(Debug, Clone, Copy)]
+pub struct Timeout {
+    /// Terminate the command after this long
+    pub after: Duration,
+    /// Kill the command when it is still running this long after it was terminated
+    pub kill_after: Duration,
+}
+
+/// Commands which are currently running in order to terminate them early.
+#[derive(Debug, Default)]
+pub struct Running(Mutex<Processes>);
+
+#[derive(Debug, Default)]
+struct Processes {
+    /// Whether they run in their own process group per pid
+    pids: HashMap<u32, bool>,
+    /// No further commands are started once they were terminated
+    stopped: bool,
+}
+
+impl Running {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Processes> {
+        self.0.lock().expect("running lock should not be poisoned")
+    }
+
+    /// Terminate all currently running commands gracefully or kill them with `force`.
+    ///
+    /// Commands which are about to start are not started anymore.
+    pub fn terminate_all(&self, force: bool) {
+        let mut processes = self.lock();
+        processes.stopped = true;
+        for (pid, own_process_group) in &processes.pids {
+            terminate(*pid, *own_process_group, force);
+        }
+    }
+
+    /// Terminate all currently running commands and kill the ones still running after `kill_after`.
+    pub fn terminate_all_then_kill(self: &Arc<Self>, kill_after: Duration) {
+        self.terminate_all(false);
+        let terminated = self.lock().pids.keys().copied().collect::<Vec<_>>();
+        let running = Arc::clone(self);
+        std::thread::Builder::new()
+            .name("kill-after".to_owned())
+            .spawn(move || {
+                std::thread::sleep(kill_after);
+                let processes = running.lock();
+                // Only the ones which did not exit in the meantime are still tracked
+                for pid in terminated {
+                    if let Some(own_process_group) = processes.pids.get(&pid) {
+                        terminate(pid, *own_process_group, true);
+                    }
+                }
+            })
+            .expect("failed to spawn thread");
+    }
 
-pub struct Command(OsCommand);
+    /// Pass a `signal` received by project-below on to the running commands.
+    ///
+    /// A signal `from_terminal` already reached the commands in the same process group.
+    /// Only commands in their own process group get it passed on then.
+    /// Commands which are about to start are not started anymore.
+    #[cfg(unix)]
+    pub fn forward(&self, signal: rustix::process::Signal, from_terminal: bool) {
+        use rustix::process::{kill_process, kill_process_group, Pid};
+        let mut processes = self.lock();
+        processes.stopped = true;
+        for (pid, own_process_group) in &processes.pids {
+            let Some(pid) = i32::try_from(*pid).ok().and_then(Pid::from_raw) else {
+                continue;
+            };
+            // The process might have exited in the meantime
+            if *own_process_group {
+                _ = kill_process_group(pid, signal);
+            } else if !from_terminal {
+                _ = kill_process(pid, signal);
+            }
+        }
+    }
+}
+
+/// Where the command reads its input from.
+#[derive(Debug, Clone)]
+pub enum Stdin {
+    Null,
+    Inherit,
+    /// Read once and passed to every command
+    Bytes(Arc<[u8]>),
+    #[cfg(unix)]
+    Tty(Arc<crate::tty::Tty>),
+}
+
+impl Stdin {
+    /// Without a `wanted` input only commands with inherited output inherit the input.
+    pub fn new(wanted: Option<&cli::Stdin>, output: CommandOutput) -> std::io::Result<Self> {
+        let wanted = wanted.cloned().unwrap_or(match output {
+            CommandOutput::Inherit => cli::Stdin::Inherit,
+            CommandOutput::Null | CommandOutput::LinePrefix | CommandOutput::Collect => {
+                cli::Stdin::None
+            }
+        });
+        Ok(match wanted {
+            cli::Stdin::None => Self::Null,
+            cli::Stdin::Inherit => Self::Inherit,
+            cli::Stdin::File(path) if path == Path::new("-") => {
+                let mut bytes = Vec::new();
+                std::io::stdin().read_to_end(&mut bytes)?;
+                Self::Bytes(bytes.into())
+            }
+            cli::Stdin::File(path) => Self::Bytes(std::fs::read(path)?.into()),
+            #[cfg(unix)]
+            cli::Stdin::Tty => {
+                if !crate::tty::Tty::is_available() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "--stdin=tty needs a terminal",
+                    ));
+                }
+                Self::Tty(Arc::default())
+            }
+            #[cfg(not(unix))]
+            cli::Stdin::Tty => Self::Inherit,
+        })
+    }
+}
+
+/// Result of a command which ran until it exited.
+pub struct Finished {
+    pub status: ExitStatus,
+    pub took: Duration,
+    pub timed_out: bool,
+    /// Why the command could not be started at all
+    pub spawn_error: Option<String>,
+    /// The command was not started as all commands were terminated in the meantime
+    pub cancelled: bool,
+}
+
+impl Finished {
+    /// The command could not be started. The status is the one of a shell in that case.
+    fn spawn_failed(program: &OsStr, err: &std::io::Error) -> Self {
+        let code = if err.kind() == std::io::ErrorKind::NotFound {
+            127
+        } else {
+            126
+        };
+        Self::not_started(
+            code,
+            format!("failed to execute process {program:?}: {err}"),
+        )
+    }
+
+    /// The command was not started because of the `reason`.
+    pub fn not_started(code: u8, reason: String) -> Self {
+        Self {
+            status: exit_status(code),
+            took: Duration::ZERO,
+            timed_out: false,
+            spawn_error: Some(reason),
+            cancelled: false,
+        }
+    }
+
+    fn cancelled() -> Self {
+        Self {
+            status: exit_status(0),
+            took: Duration::ZERO,
+            timed_out: false,
+            spawn_error: None,
+            cancelled: true,
+        }
+    }
+}
+
+/// Whether a command in its own process group may take over the foreground of the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Foreground {
+    Denied,
+    /// No other command runs at the same time
+    Allowed,
+    /// It runs in the foreground of the terminal
+    Taken,
+}
+
+pub struct Command {
+    inner: OsCommand,
+    timeout: Option<Timeout>,
+    running: Option<Arc<Running>>,
+    own_process_group: bool,
+    foreground: Foreground,
+    /// Why the command can not be started, known before starting it
+    unstartable: Option<std::io::Error>,
+    stdin: Stdin,
+    pty: bool,
+}
 
 impl Command {
-    pub fn new(raw: &[OsString], working_dir: &Path) -> Self {
-        let mut command = OsCommand::new(&raw[0]);
-        command.args(&raw[1..]).current_dir(working_dir);
-        Self(command)
+    /// With a `shell` the arguments are joined and run via `shell -c`.
+    pub fn new(raw: &[OsString], working_dir: &Path, shell: Option<&OsStr>) -> Self {
+        let mut command = shell.map_or_else(
+            || {
+                let mut command = OsCommand::new(&raw[0]);
+                command.args(&raw[1..]);
+                command
+            },
+            |shell| {
+                let mut command = OsCommand::new(shell);
+                command.arg("-c").arg(crate::shell::join(raw));
+                command
+            },
+        );
+        command.current_dir(working_dir);
+        Self {
+            inner: command,
+            timeout: None,
+            running: None,
+            own_process_group: false,
+            foreground: Foreground::Denied,
+            unstartable: None,
+            stdin: Stdin::Null,
+            pty: false,
+        }
     }
 
-    #[track_caller]
-    fn failed(&self, err: &std::io::Error) -> ! {
-        let program = self.0.get_program();
-        panic!("failed to execute process {program:?}: {err}");
+    /// Set additional environment variables for the command.
+    pub fn envs<K, V>(mut self, vars: &[(K, V)]) -> Self
+    where
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        for (key, value) in vars {
+            self.inner.env(key, value);
+        }
+        self
     }
 
-    pub fn inherit(mut self) -> (ExitStatus, Duration) {
-        let start = Instant::now();
-        let status = self
-            .0
-            .env("PAGER", "cat")
-            .status()
-            .unwrap_or_else(|err| self.failed(&err));
-        let took = start.elapsed();
-        (status, took)
+    /// Terminate the command and all of its child processes when it takes too long.
+    ///
+    /// On Unix the command is run in its own process group for this.
+    /// A command reading from the terminal gets the foreground of the terminal for its process group when it may, see [`Self::foreground`].
+    /// Otherwise it stays in the foreground process group of project-below and only the command itself is terminated.
+    pub fn timeout(mut self, timeout: Option<Timeout>) -> Self {
+        if timeout.is_some() {
+            self.own_process_group();
+        }
+        self.timeout = timeout;
+        self
     }
 
-    pub fn null(mut self) -> (ExitStatus, Duration) {
-        let start = Instant::now();
-        let status = self
-            .0
-            .stdin(Stdio::null())
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .unwrap_or_else(|err| self.failed(&err));
-        let took = start.elapsed();
-        (status, took)
-    }
-
-    pub fn lineprefixed(mut self, prefix: &str) -> (ExitStatus, Duration) {
-        let start = Instant::now();
+    /// With [`Stdin::Tty`] the command is run in its own process group.
+    pub fn stdin(mut self, stdin: Stdin) -> Self {
+        #[cfg(unix)]
+        if matches!(stdin, Stdin::Tty(_)) {
+            self.own_process_group();
+        }
+        self.stdin = stdin;
+        self
+    }
+
+    /// Shell-quoted command line which does the same like running this command with the `output`.
+    ///
+    /// It changes into the working directory and sets the additional environment variables.
+    /// Arguments which are not valid UTF-8 are quoted with their exact bytes.
+    pub fn describe(&self, output: CommandOutput) -> String {
+        let mut line = OsString::new();
+        if let Some(dir) = self.inner.get_current_dir() {
+            line.push("cd ");
+            line.push(crate::shell::quote_display(dir.as_os_str()));
+            line.push(" && ");
+        }
+        let mut envs = self
+            .inner
+            .get_envs()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect::<Vec<_>>();
+        if matches!(output, CommandOutput::Inherit) {
+            let (key, value) = INHERIT_ENV;
+            envs.retain(|(existing, _)| *existing != key);
+            envs.push((OsStr::new(key), OsStr::new(value)));
+        }
+        for (key, value) in envs {
+            line.push(crate::shell::quote_display(key));
+            line.push("=");
+            line.push(crate::shell::quote_display(value));
+            line.push(" ");
+        }
+        line.push(crate::shell::quote_display(self.inner.get_program()));
+        for arg in self.inner.get_args() {
+            line.push(" ");
+            line.push(crate::shell::quote_display(arg));
+        }
+        line.into_string()
+            .expect("shell quoted arguments should be valid UTF-8")
+    }
+
+    /// Run the command in a pseudo-terminal on Unix when its output is read by project-below.
+    ///
+    /// Its stdout and stderr both end up in the terminal.
+    pub const fn pty(mut self, pty: bool) -> Self {
+        self.pty = pty;
+        self
+    }
+
+    /// Apply the resource `limits` before the command runs.
+    ///
+    /// Environment variables have to be set before.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        if let Err(err) = limits.wrap(&mut self.inner) {
+            self.unstartable = Some(err);
+        }
+        self
+    }
+
+    /// Keep track of the command while it runs to be able to terminate it early.
+    pub fn track(mut self, running: Arc<Running>) -> Self {
+        self.running = Some(running);
+        self
+    }
+
+    /// Run the command in its own process group on Unix.
+    ///
+    /// This way all of its child processes can be terminated together.
+    pub fn process_group(mut self, own: bool) -> Self {
+        if own {
+            self.own_process_group();
+        }
+        self
+    }
+
+    fn own_process_group(&mut self) {
+        self.own_process_group = cfg!(unix);
+    }
+
+    /// Allow the command in its own process group to take over the foreground of the terminal when it reads from it.
+    ///
+    /// Only one process group can be in the foreground, so this is only allowed when no other command runs at the same time.
+    /// Commands running in parallel stay in the process group of project-below instead.
+    pub const fn foreground(mut self, allowed: bool) -> Self {
+        self.foreground = if allowed {
+            Foreground::Allowed
+        } else {
+            Foreground::Denied
+        };
+        self
+    }
+
+    /// A command in a background process group is stopped by SIGTTIN once it reads from the terminal.
+    const fn reads_terminal(&self, stdin_is_terminal: bool) -> bool {
+        stdin_is_terminal && matches!(self.stdin, Stdin::Inherit)
+    }
+
+    fn spawn(&mut self) -> Result<Child, Finished> {
+        let reads_terminal =
+            self.reads_terminal(std::io::IsTerminal::is_terminal(&std::io::stdin()));
+        if self.own_process_group && reads_terminal {
+            #[cfg(unix)]
+            if self.foreground == Foreground::Allowed && crate::tty::Tty::is_foreground() {
+                self.foreground = Foreground::Taken;
+            }
+            if self.foreground != Foreground::Taken {
+                self.own_process_group = false;
+            }
+        }
+        #[cfg(unix)]
+        if self.own_process_group {
+            std::os::unix::process::CommandExt::process_group(&mut self.inner, 0);
+        }
+        self.inner.stdin(match self.stdin {
+            Stdin::Null => Stdio::null(),
+            Stdin::Bytes(_) => Stdio::piped(),
+            #[cfg(unix)]
+            Stdin::Tty(_) => Stdio::inherit(),
+            Stdin::Inherit => Stdio::inherit(),
+        });
+        // Checked under the same lock as the registration to not start after `terminate_all`
+        let mut processes = self.running.as_ref().map(|running| running.lock());
+        if processes
+            .as_ref()
+            .is_some_and(|processes| processes.stopped)
+        {
+            return Err(Finished::cancelled());
+        }
+        if let Some(err) = &self.unstartable {
+            return Err(Finished::spawn_failed(self.inner.get_program(), err));
+        }
         let mut child = self
-            .0
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
+            .inner
             .spawn()
-            .unwrap_or_else(|err| self.failed(&err));
+            .map_err(|err| Finished::spawn_failed(self.inner.get_program(), &err))?;
+        if let Some(processes) = &mut processes {
+            processes.pids.insert(child.id(), self.own_process_group);
+        }
+        drop(processes);
+        if let Stdin::Bytes(bytes) = &self.stdin {
+            let mut stdin = child.stdin.take().expect("stdin should be piped");
+            let bytes = Arc::clone(bytes);
+            std::thread::Builder::new()
+                .name("command-stdin".to_owned())
+                .spawn(move || {
+                    // The command does not have to read all of it
+                    _ = stdin.write_all(&bytes);
+                })
+                .expect("failed to spawn thread");
+        }
+        Ok(child)
+    }
+
+    #[cfg(unix)]
+    fn spawn_pty(
+        &mut self,
+        reserved_columns: usize,
+    ) -> Result<(Child, crate::pty::PtyReader), Finished> {
+        let pty = crate::pty::Pty::open(reserved_columns)
+            .map_err(|err| Finished::spawn_failed(self.inner.get_program(), &err))?;
+        let (stdout, stderr) = pty
+            .stdio()
+            .map_err(|err| Finished::spawn_failed(self.inner.get_program(), &err))?;
+        self.inner.stdout(stdout).stderr(stderr);
+        let child = self.spawn();
+        // Close the own copies of the terminal to notice when the command is done with it
+        self.inner.stdout(Stdio::null()).stderr(Stdio::null());
+        Ok((child?, pty.reader()))
+    }
+
+    /// Wait for the `child` with `wait` while enforcing the timeout.
+    fn supervise<T, F>(&self, child: Child, wait: F) -> (T, bool)
+    where
+        F: FnOnce(Child) -> T,
+    {
+        let pid = child.id();
+        let result = self.with_timeout(pid, || self.with_job_control(pid, || wait(child)));
+        if let Some(running) = &self.running {
+            running.lock().pids.remove(&pid);
+        }
+        result
+    }
 
-        std::thread::scope(|scope| {
-            scope_spawn(scope, "command-stdout", || {
-                for line in child.stdout.take().unwrap().byte_lines() {
+    #[cfg_attr(not(unix), allow(clippy::unused_self))]
+    fn with_job_control<T, F>(&self, pid: u32, wait: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        #[cfg(unix)]
+        if let Stdin::Tty(tty) = &self.stdin {
+            return std::thread::scope(|scope| {
+                scope_spawn(scope, "command-tty", || tty.job_control(pid));
+                wait()
+            });
+        }
+        #[cfg(unix)]
+        if self.foreground == Foreground::Taken {
+            return std::thread::scope(|scope| {
+                scope_spawn(scope, "command-tty", || {
+                    crate::tty::Tty::default().foreground(pid);
+                });
+                wait()
+            });
+        }
+        _ = pid;
+        wait()
+    }
+
+    fn with_timeout<T, F>(&self, pid: u32, wait: F) -> (T, bool)
+    where
+        F: FnOnce() -> T,
+    {
+        let Some(timeout) = self.timeout else {
+            return (wait(), false);
+        };
+        let timed_out = AtomicBool::new(false);
+        let (done_tx, done_rx) = bounded::<()>(0);
+        let result = std::thread::scope(|scope| {
+            scope_spawn(scope, "command-timeout", || {
+                if done_rx.recv_timeout(timeout.after) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+                timed_out.store(true, Ordering::Relaxed);
+                terminate(pid, self.own_process_group, false);
+                if done_rx.recv_timeout(timeout.kill_after) == Err(RecvTimeoutError::Timeout) {
+                    terminate(pid, self.own_process_group, true);
+                }
+            });
+            let result = wait();
+            drop(done_tx);
+            result
+        });
+        (result, timed_out.into_inner())
+    }
+
+    pub fn inherit(mut self) -> Finished {
+        let start = Instant::now();
+        let (key, value) = INHERIT_ENV;
+        self.inner.env(key, value);
+        let child = match self.spawn() {
+            Ok(child) => child,
+            Err(finished) => return finished,
+        };
+        let (status, timed_out) = self.supervise(child, |mut child| child.wait());
+        let status = status.expect("failed to wait on child process");
+        self.exited(status, start, timed_out)
+    }
+
+    pub fn null(mut self) -> Finished {
+        let start = Instant::now();
+        self.inner.stdout(Stdio::null()).stderr(Stdio::null());
+        let child = match self.spawn() {
+            Ok(child) => child,
+            Err(finished) => return finished,
+        };
+        let (status, timed_out) = self.supervise(child, |mut child| child.wait());
+        let status = status.expect("failed to wait on child process");
+        self.exited(status, start, timed_out)
+    }
+
+    pub fn lineprefixed(mut self, prefix: &str) -> Finished {
+        let start = Instant::now();
+        #[cfg(unix)]
+        if self.pty {
+            let (child, reader) = match self.spawn_pty(prefix.chars().count()) {
+                Ok(spawned) => spawned,
+                Err(finished) => return finished,
+            };
+            let (status, timed_out) = self.supervise(child, |mut child| {
+                for line in reader.byte_lines() {
                     let mut stdout = std::io::stdout().lock();
                     stdout.write_all(prefix.as_bytes()).unwrap();
                     stdout.write_all(&line).unwrap();
                     stdout.write_all(b"\n").unwrap();
                 }
+                child.wait()
             });
-            scope_spawn(scope, "command-stderr", || {
-                for line in child.stderr.take().unwrap().byte_lines() {
-                    let mut stderr = std::io::stderr().lock();
-                    stderr.write_all(prefix.as_bytes()).unwrap();
-                    stderr.write_all(&line).unwrap();
-                    stderr.write_all(b"\n").unwrap();
-                }
+            return self.exited(
+                status.expect("failed to wait on child process"),
+                start,
+                timed_out,
+            );
+        }
+        self.inner.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let child = match self.spawn() {
+            Ok(child) => child,
+            Err(finished) => return finished,
+        };
+
+        let (output, timed_out) = self.supervise(child, |mut child| {
+            std::thread::scope(|scope| {
+                scope_spawn(scope, "command-stdout", || {
+                    for line in child.stdout.take().unwrap().byte_lines() {
+                        let mut stdout = std::io::stdout().lock();
+                        stdout.write_all(prefix.as_bytes()).unwrap();
+                        stdout.write_all(&line).unwrap();
+                        stdout.write_all(b"\n").unwrap();
+                    }
+                });
+                scope_spawn(scope, "command-stderr", || {
+                    for line in child.stderr.take().unwrap().byte_lines() {
+                        let mut stderr = std::io::stderr().lock();
+                        stderr.write_all(prefix.as_bytes()).unwrap();
+                        stderr.write_all(&line).unwrap();
+                        stderr.write_all(b"\n").unwrap();
+                    }
+                });
             });
+            child.wait_with_output()
         });
-
-        let output = child
-            .wait_with_output()
-            .expect("failed to wait on child process");
-        let took = start.elapsed();
+        let output = output.expect("failed to wait on child process");
 
         assert!(output.stdout.is_empty(), "stdout should be empty");
         assert!(output.stderr.is_empty(), "stderr should be empty");
 
-        (output.status, took)
+        self.exited(output.status, start, timed_out)
+    }
+
+    /// Result of the command which exited with `status` after it was started at `start`.
+    ///
+    /// Ctrl+C in the terminal only reaches a command in the foreground.
+    /// When it ended the command, it is passed on to project-below like it was in the foreground as well.
+    fn exited(&self, status: ExitStatus, start: Instant, timed_out: bool) -> Finished {
+        #[cfg(unix)]
+        if self.foreground == Foreground::Taken {
+            use rustix::process::{getpid, kill_process, Signal};
+            if crate::interrupt::killed_by(status, Signal::INT.as_raw()) {
+                _ = kill_process(getpid(), Signal::INT);
+            }
+        }
+        Finished {
+            status,
+            took: start.elapsed(),
+            timed_out,
+            spawn_error: None,
+            cancelled: false,
+        }
     }
 
-    pub fn output(mut self) -> (Output, Duration) {
+    const fn empty_output(finished: &Finished) -> Output {
+        Output {
+            status: finished.status,
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        }
+    }
+
+    pub fn output(mut self) -> (Output, Finished) {
         let start = Instant::now();
-        let output = self.0.output().unwrap_or_else(|err| self.failed(&err));
-        let took = start.elapsed();
-        (output, took)
+        #[cfg(unix)]
+        if self.pty {
+            let spawned = self.spawn_pty(0);
+            let output = spawned.map(|(child, mut reader)| {
+                self.supervise(child, |mut child| {
+                    let mut stdout = Vec::new();
+                    reader
+                        .read_to_end(&mut stdout)
+                        .expect("should be able to read the output");
+                    child.wait().map(|status| Output {
+                        status,
+                        stdout,
+                        stderr: Vec::new(),
+                    })
+                })
+            });
+            return match output {
+                Ok((output, timed_out)) => {
+                    let output = output.expect("failed to wait on child process");
+                    let finished = self.exited(output.status, start, timed_out);
+                    (output, finished)
+                }
+                Err(finished) => (Self::empty_output(&finished), finished),
+            };
+        }
+        self.inner.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let child = match self.spawn() {
+            Ok(child) => child,
+            Err(finished) => return (Self::empty_output(&finished), finished),
+        };
+        let (output, timed_out) = self.supervise(child, Child::wait_with_output);
+        let output = output.expect("failed to wait on child process");
+        let finished = self.exited(output.status, start, timed_out);
+        (output, finished)
+    }
+}
+
+#[cfg(unix)]
+fn exit_status(code: u8) -> ExitStatus {
+    std::os::unix::process::ExitStatusExt::from_raw(i32::from(code) << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: u8) -> ExitStatus {
+    std::os::windows::process::ExitStatusExt::from_raw(code.into())
+}
+
+/// Terminate the process group of `pid` gracefully or kill it with `force`.
+///
+/// Only the process itself is signalled when it does not have its own process group.
+#[cfg(unix)]
+fn terminate(pid: u32, own_process_group: bool, force: bool) {
+    use rustix::process::{kill_process, kill_process_group, Pid, Signal};
+    let signal = if force { Signal::KILL } else { Signal::TERM };
+    if let Some(pid) = i32::try_from(pid).ok().and_then(Pid::from_raw) {
+        // The process might have exited in the meantime
+        if !own_process_group || kill_process_group(pid, signal).is_err() {
+            _ = kill_process(pid, signal);
+        }
     }
 }
 
+/// Terminate the process tree of `pid` gracefully or kill it with `force`.
+#[cfg(not(unix))]
+fn terminate(pid: u32, _own_process_group: bool, force: bool) {
+    let mut taskkill = OsCommand::new("taskkill");
+    taskkill.arg("/PID").arg(pid.to_string()).arg("/T");
+    if force {
+        taskkill.arg("/F");
+    }
+    // The process might have exited in the meantime
+    _ = taskkill
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
 fn scope_spawn<'scope, F>(scope: &'scope Scope<'scope, '_>, name: &str, func: F)
 where
     F: FnOnce() + Send + 'scope,
@@ -103,3 +704,53 @@ where
         .spawn_scoped(scope, func)
         .expect("failed to spawn thread");
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sleep(stdin: Stdin) -> Command {
+        let raw = [OsString::from("sleep 10")];
+        Command::new(&raw, Path::new("."), Some(OsStr::new("sh")))
+            .stdin(stdin)
+            .timeout(Some(Timeout {
+                after: Duration::from_millis(100),
+                kill_after: Duration::from_secs(10),
+            }))
+    }
+
+    #[test]
+    fn describe() {
+        use std::os::unix::ffi::OsStrExt;
+        let raw = [
+            OsString::from("echo"),
+            OsStr::from_bytes(b"caf\xe9").to_os_string(),
+            OsString::from("it's"),
+        ];
+        let command = Command::new(&raw, Path::new("/tmp/a b"), None).envs(&[("PAGER", "less")]);
+        assert_eq!(
+            command.describe(CommandOutput::Null),
+            r"cd '/tmp/a b' && PAGER=less echo $'caf\xe9' 'it'\''s'"
+        );
+        assert_eq!(
+            command.describe(CommandOutput::Inherit),
+            r"cd '/tmp/a b' && PAGER=cat echo $'caf\xe9' 'it'\''s'"
+        );
+    }
+
+    #[test]
+    fn reading_the_terminal() {
+        assert!(sleep(Stdin::Inherit).reads_terminal(true));
+        assert!(!sleep(Stdin::Inherit).reads_terminal(false));
+        assert!(!sleep(Stdin::Null).reads_terminal(true));
+    }
+
+    #[test]
+    fn timeout_terminates() {
+        for stdin in [Stdin::Inherit, Stdin::Null] {
+            let finished = sleep(stdin).null();
+            assert!(finished.timed_out);
+            assert!(finished.took < Duration::from_secs(5));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::path_style::PathStyle;
+
+#[cfg(unix)]
+const TTY_IN: &str = "/dev/tty";
+#[cfg(unix)]
+const TTY_OUT: &str = "/dev/tty";
+#[cfg(windows)]
+const TTY_IN: &str = "CONIN$";
+#[cfg(windows)]
+const TTY_OUT: &str = "CONOUT$";
+
+/// Asks on the terminal whether to run the command in a project.
+///
+/// The terminal is used directly so this works even when stdin is redirected.
+pub struct Prompt {
+    input: BufReader<File>,
+    output: File,
+}
+
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
+impl Prompt {
+    pub fn open() -> std::io::Result<Self> {
+        let input = File::open(TTY_IN)?;
+        let output = File::options().write(true).open(TTY_OUT)?;
+        Ok(Self {
+            input: BufReader::new(input),
+            output,
+        })
+    }
+
+    fn ask(&mut self, question: &str) -> Answer {
+        loop {
+            _ = write!(self.output, "{question} [y]es / [n]o / [a]ll / [q]uit ");
+            _ = self.output.flush();
+            let mut line = String::new();
+            match self.input.read_line(&mut line) {
+                Ok(0) | Err(_) => return Answer::Quit,
+                Ok(_) => {}
+            }
+            match line.trim().to_ascii_lowercase().as_str() {
+                "y" | "yes" => return Answer::Yes,
+                "n" | "no" => return Answer::No,
+                "a" | "all" => return Answer::All,
+                "q" | "quit" => return Answer::Quit,
+                _ => {}
+            }
+        }
+    }
+
+    /// Pass on the projects one by one once they are confirmed.
+    ///
+    /// Confirmed projects already run while the next one is asked for.
+    pub fn confirm(mut self, rx: Receiver<PathBuf>, path_style: PathStyle) -> Receiver<PathBuf> {
+        let (tx, confirmed) = unbounded();
+        std::thread::Builder::new()
+            .name("confirm".to_owned())
+            .spawn(move || {
+                let mut all = false;
+                for path in rx {
+                    if !all {
+                        let question = format!("Run in {}?", path_style.path(&path));
+                        match self.ask(&question) {
+                            Answer::Yes => {}
+                            Answer::No => continue,
+                            Answer::All => all = true,
+                            Answer::Quit => return,
+                        }
+                    }
+                    if tx.send(path).is_err() {
+                        return;
+                    }
+                }
+            })
+            .expect("failed to spawn thread");
+        confirmed
+    }
+}
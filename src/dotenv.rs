@@ -0,0 +1,117 @@
+use std::path::Path;
+
+/// Read a dotenv file with `KEY=VALUE` lines.
+///
+/// Empty lines and `#` comments are skipped and a leading `export` is allowed.
+/// Values can be single quoted (literal) or double quoted (with `\n`, `\"` and `\\` escapes).
+/// Variables within values are not expanded.
+pub fn read(path: &Path) -> std::io::Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)?;
+    parse(&content).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+fn parse(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut result = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("line {number}: expected KEY=VALUE"));
+        };
+        let key = key.trim();
+        let is_valid_key = !key.is_empty()
+            && key
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || char == '_');
+        if !is_valid_key {
+            return Err(format!("line {number}: invalid key {key:?}"));
+        }
+        let value = unquote(value.trim()).map_err(|err| format!("line {number}: {err}"))?;
+        result.push((key.to_owned(), value));
+    }
+    Ok(result)
+}
+
+fn unquote(raw: &str) -> Result<String, &'static str> {
+    if let Some(rest) = raw.strip_prefix('\'') {
+        let (value, _) = rest.split_once('\'').ok_or("unterminated single quote")?;
+        return Ok(value.to_owned());
+    }
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = rest.chars();
+        while let Some(char) = chars.next() {
+            match char {
+                '"' => return Ok(value),
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(other) => value.push(other),
+                    None => break,
+                },
+                char => value.push(char),
+            }
+        }
+        return Err("unterminated double quote");
+    }
+    let value = raw.split_once(" #").map_or(raw, |(value, _)| value);
+    Ok(value.trim_end().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn case(content: &str, expected: &[(&str, &str)]) {
+        let expected = expected
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+            .collect::<Vec<_>>();
+        assert_eq!(parse(content).unwrap(), expected);
+    }
+
+    #[test]
+    fn plain() {
+        case("FOO=bar\nBAZ = qux ", &[("FOO", "bar"), ("BAZ", "qux")]);
+    }
+
+    #[test]
+    fn comments_and_empty_lines() {
+        case(
+            "# comment\n\nFOO=bar # trailing\nURL=http://host/#anchor",
+            &[("FOO", "bar"), ("URL", "http://host/#anchor")],
+        );
+    }
+
+    #[test]
+    fn export() {
+        case("export FOO=bar", &[("FOO", "bar")]);
+    }
+
+    #[test]
+    fn quoted() {
+        case(
+            r#"A='it is $HOME' # comment
+B="line\nbreak \"quoted\""
+C="""#,
+            &[
+                ("A", "it is $HOME"),
+                ("B", "line\nbreak \"quoted\""),
+                ("C", ""),
+            ],
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse("FOO").is_err());
+        assert!(parse("FOO BAR=baz").is_err());
+        assert!(parse("FOO='bar").is_err());
+        assert!(parse(r#"FOO="bar"#).is_err());
+    }
+}
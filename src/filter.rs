@@ -0,0 +1,116 @@
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::check_dir_is_project::Pattern;
+use crate::command::{Command, Timeout};
+use crate::placeholder;
+use crate::project::Project;
+
+/// A command which decides whether a project is kept.
+pub struct Filter {
+    pub command: OsString,
+    pub shell: OsString,
+    pub invert: bool,
+    pub timeout: Option<Timeout>,
+    pub base_dir: PathBuf,
+    pub patterns: Vec<Pattern>,
+}
+
+impl Filter {
+    /// Run the command in every project in parallel and only pass on the projects where it succeeded.
+    ///
+    /// The output of the command is discarded.
+    /// Only the first `max_results` kept projects are passed on. No further commands are started then and the walk stops.
+    pub fn filter(
+        self,
+        rx: Receiver<PathBuf>,
+        threads: NonZeroUsize,
+        max_results: Option<NonZeroUsize>,
+    ) -> Receiver<PathBuf> {
+        let (tx, kept) = unbounded();
+        let filter = Arc::new(self);
+        let amount = Arc::new(AtomicUsize::new(0));
+        let max_results = max_results.map_or(usize::MAX, NonZeroUsize::get);
+        // The walk stops once all receivers are gone after the `max_results`
+        for rx in std::iter::repeat(rx).take(threads.get()) {
+            let tx = tx.clone();
+            let filter = Arc::clone(&filter);
+            let amount = Arc::clone(&amount);
+            std::thread::Builder::new()
+                .name("filter".to_owned())
+                .spawn(move || {
+                    for path in rx {
+                        if amount.load(Ordering::Relaxed) >= max_results {
+                            return;
+                        }
+                        if !filter.keep(&path) {
+                            continue;
+                        }
+                        let before = amount.fetch_add(1, Ordering::Relaxed);
+                        if before >= max_results || tx.send(path).is_err() {
+                            return;
+                        }
+                    }
+                })
+                .expect("failed to spawn thread");
+        }
+        kept
+    }
+
+    /// A command which could not be started drops the project, also with `invert`.
+    fn keep(&self, path: &Path) -> bool {
+        let project = Project::new(path, &self.base_dir, &self.patterns);
+        let raw = placeholder::expand(std::slice::from_ref(&self.command), &project, true);
+        let finished = Command::new(&raw, path, Some(&self.shell))
+            .timeout(self.timeout)
+            .null();
+        if let Some(spawn_error) = &finished.spawn_error {
+            eprintln!(
+                "project-below: --filter-exec: {spawn_error} in {}",
+                path.display()
+            );
+            return false;
+        }
+        finished.status.success() != self.invert
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn keep(command: &str, shell: &str, invert: bool) -> bool {
+        let filter = Filter {
+            command: command.into(),
+            shell: shell.into(),
+            invert,
+            timeout: None,
+            base_dir: PathBuf::from("."),
+            patterns: Vec::new(),
+        };
+        filter.keep(Path::new("."))
+    }
+
+    #[test]
+    fn keeps_successful() {
+        assert!(keep("true", "sh", false));
+        assert!(!keep("false", "sh", false));
+    }
+
+    #[test]
+    fn invert_keeps_failed() {
+        assert!(!keep("true", "sh", true));
+        assert!(keep("false", "sh", true));
+    }
+
+    #[test]
+    fn spawn_error_is_not_kept() {
+        assert!(!keep("true", "/nonexistent/sh", false));
+        assert!(!keep("true", "/nonexistent/sh", true));
+    }
+}
@@ -1,11 +1,11 @@
 use std::io::Write;
 use std::num::NonZeroUsize;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{ExitStatus, Output};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
 
-use crate::cli::CommandResult;
+use crate::cli::{CommandOutput, CommandResult};
+use crate::command::{Command, Finished};
 use crate::path_style::{DPath, PathStyle};
 
 pub struct Config {
@@ -14,6 +14,8 @@ pub struct Config {
     line_prefix_width: usize,
     no_header: bool,
     result: CommandResult,
+    /// Headers show the paths shell-quoted
+    quote: bool,
 
     first: AtomicBool,
     need_linesplit: AtomicBool,
@@ -26,6 +28,7 @@ impl Config {
         line_prefix_width: usize,
         no_header: bool,
         result: CommandResult,
+        quote: bool,
     ) -> Self {
         Self {
             path_style,
@@ -33,20 +36,68 @@ impl Config {
             line_prefix_width,
             no_header,
             result,
+            quote,
 
             first: AtomicBool::new(true),
             need_linesplit: AtomicBool::new(false),
         }
     }
 
+    pub const fn path<'a>(&'a self, path: &'a Path) -> DPath<'a> {
+        self.path_style.path(path)
+    }
+
+    /// Summary after the run was stopped early by a `signal` or `--fail-fast`.
+    ///
+    /// The walk stopped as well, so only the found projects which were `not_started` are known.
+    pub fn stopped(
+        &self,
+        signal: Option<i32>,
+        results: &[(PathBuf, ExitStatus)],
+        interrupted: &[PathBuf],
+        not_started: &[PathBuf],
+    ) {
+        if let Some(signal) = signal {
+            let failed = results
+                .iter()
+                .filter(|(_, status)| !status.success())
+                .count()
+                - interrupted.len();
+            let completed = results.len() - failed - interrupted.len();
+            eprintln!(
+                "project-below: interrupted by {}: {completed} completed, {failed} failed, {} interrupted, {} not started",
+                crate::interrupt::name(signal),
+                interrupted.len(),
+                not_started.len(),
+            );
+        } else if !not_started.is_empty() {
+            eprintln!(
+                "project-below: stopped after the first failure: {} not started",
+                not_started.len()
+            );
+        }
+        for path in interrupted {
+            eprintln!("interrupted: {}", self.path(path));
+        }
+        for path in not_started {
+            eprintln!("not started: {}", self.path(path));
+        }
+    }
+
     pub const fn create<'a>(&'a self, path: &'a Path) -> Harness<'a> {
-        Harness { config: self, path }
+        Harness {
+            config: self,
+            path,
+            step: None,
+        }
     }
 }
 
 pub struct Harness<'a> {
     config: &'a Config,
     path: &'a Path,
+    /// Index of the `--then` step and the amount of steps
+    step: Option<(usize, usize)>,
 }
 
 impl<'a> Harness<'a> {
@@ -54,15 +105,43 @@ impl<'a> Harness<'a> {
         self.config.path_style.path(self.path)
     }
 
+    /// The same harness for the step `index` of `total` steps. A single step is not mentioned.
+    pub const fn step(&self, index: usize, total: usize) -> Self {
+        Self {
+            config: self.config,
+            path: self.path,
+            step: if total > 1 {
+                Some((index, total))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn header(&self) -> String {
+        if self.config.quote {
+            self.path().quoted()
+        } else {
+            self.path().to_string()
+        }
+    }
+
+    fn step_suffix(&self) -> String {
+        self.step
+            .map(|(index, total)| format!(" step {}/{total}", index + 1))
+            .unwrap_or_default()
+    }
+
     pub fn inherit_header(&self) {
-        if self.config.multithreaded || self.config.no_header {
+        let later_step = matches!(self.step, Some((index, _)) if index > 0);
+        if self.config.multithreaded || self.config.no_header || later_step {
             return;
         }
         let first = self.config.first.swap(false, Ordering::Relaxed);
         if !first {
             println!();
         }
-        println!("{}", self.path());
+        println!("{}", self.header());
     }
 
     pub fn line_prefix(&self) -> String {
@@ -90,7 +169,7 @@ impl<'a> Harness<'a> {
         }
         if let Some(last) = output.stdout.last() {
             if !self.config.no_header {
-                println!("{}  stdout:", self.path());
+                println!("{}  stdout:", self.header());
             }
             std::io::stdout().write_all(&output.stdout).unwrap();
             if *last != b'\n' {
@@ -99,7 +178,7 @@ impl<'a> Harness<'a> {
         }
         if let Some(last) = output.stderr.last() {
             if !self.config.no_header {
-                eprintln!("{}  stderr:", self.path());
+                eprintln!("{}  stderr:", self.header());
             }
             std::io::stderr().write_all(&output.stderr).unwrap();
             if *last != b'\n' {
@@ -108,10 +187,35 @@ impl<'a> Harness<'a> {
         }
     }
 
-    pub fn result(&self, took: Duration, status: ExitStatus) {
-        if self.config.result.print(status.success()) {
-            let took = crate::took::Took(took);
-            println!("took {took}  {status} in {}", self.path());
+    /// Show what would be run with the `output` instead of running it.
+    pub fn dry_run(&self, command: &Command, output: CommandOutput) {
+        let mut stdout = std::io::stdout().lock();
+        _ = writeln!(stdout, "# {}{}", self.path(), self.step_suffix());
+        _ = writeln!(stdout, "{}", command.describe(output));
+    }
+
+    /// `attempts` is the amount of times the command was run until it `finished`.
+    ///
+    /// Why a command could not be started is always printed to stderr, even without the result.
+    pub fn result(&self, finished: &Finished, attempts: u32) {
+        let path = self.path();
+        let step = self.step_suffix();
+        if let Some(spawn_error) = &finished.spawn_error {
+            eprintln!("project-below: {spawn_error} in {path}{step}");
+        }
+        if self.config.result.print(finished.status.success()) {
+            let took = crate::took::Took(finished.took);
+            let timed_out = if finished.timed_out {
+                "timed out, "
+            } else {
+                ""
+            };
+            let status = format!("{timed_out}{}", finished.status);
+            if attempts > 1 {
+                println!("took {took}  {status} after {attempts} attempts in {path}{step}");
+            } else {
+                println!("took {took}  {status} in {path}{step}");
+            }
         }
     }
 }
@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::cache::{Records, Tag};
+
+const VERSION: &str = "project-below-index-1";
+
+/// Directories modified this recently might still change within the same mtime granularity.
+/// They are not cached.
+const RACY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    /// For example broken symlinks
+    Other,
+}
+
+impl EntryKind {
+    fn of(path: &Path) -> Self {
+        match path.metadata() {
+            Ok(meta) if meta.is_file() => Self::File,
+            Ok(meta) if meta.is_dir() => Self::Directory,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl Tag for EntryKind {
+    const CHARS: &'static [(Self, char)] = &[
+        (Self::File, 'f'),
+        (Self::Directory, 'd'),
+        (Self::Other, 'o'),
+    ];
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub name: OsString,
+    pub kind: EntryKind,
+}
+
+#[derive(Debug, Clone)]
+struct CachedDir {
+    mtime: SystemTime,
+    entries: Arc<[Entry]>,
+}
+
+/// On-disk cache of directory entries to skip reading unchanged directories again while checking the patterns.
+///
+/// A cached directory is valid as long as its modification time did not change.
+/// Adding, removing or renaming an entry of a directory updates its modification time.
+/// The directory walk itself still reads every directory, only the pattern checks below a directory use the index.
+pub struct Index {
+    base_dir: PathBuf,
+    file: Option<PathBuf>,
+    known: HashMap<PathBuf, CachedDir>,
+    /// Directories used in this run. They replace the known ones when saving.
+    used: Mutex<HashMap<PathBuf, CachedDir>>,
+    changed: AtomicBool,
+}
+
+impl Index {
+    /// Always read the directories from the filesystem.
+    pub fn disabled(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            file: None,
+            known: HashMap::new(),
+            used: Mutex::new(HashMap::new()),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Load the index of the `base_dir` from the cache directory.
+    /// With `refresh` the existing index is ignored and replaced.
+    pub fn load(base_dir: &Path, refresh: bool) -> Self {
+        let Some(file) = index_file(base_dir) else {
+            return Self::disabled(base_dir);
+        };
+        let known = if refresh {
+            HashMap::new()
+        } else {
+            std::fs::read_to_string(&file)
+                .ok()
+                .and_then(|content| parse(&content))
+                .unwrap_or_default()
+        };
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            file: Some(file),
+            known,
+            used: Mutex::new(HashMap::new()),
+            changed: AtomicBool::new(refresh),
+        }
+    }
+
+    pub fn read_dir(&self, dir: &Path) -> std::io::Result<Arc<[Entry]>> {
+        if self.file.is_none() {
+            return read_entries(dir);
+        }
+        let mtime = dir.metadata()?.modified()?;
+        let key = dir.strip_prefix(&self.base_dir).unwrap_or(dir);
+        if let Some(cached) = self.known.get(key) {
+            if cached.mtime == mtime {
+                self.use_dir(key, cached.clone());
+                return Ok(cached.entries.clone());
+            }
+        }
+
+        let entries = read_entries(dir)?;
+        let is_racy = SystemTime::now()
+            .duration_since(mtime)
+            .map_or(true, |age| age < RACY);
+        let is_utf8 =
+            key.to_str().is_some() && entries.iter().all(|entry| entry.name.to_str().is_some());
+        if !is_racy && is_utf8 {
+            let cached = CachedDir {
+                mtime,
+                entries: entries.clone(),
+            };
+            self.use_dir(key, cached);
+        }
+        self.changed.store(true, Ordering::Relaxed);
+        Ok(entries)
+    }
+
+    fn use_dir(&self, key: &Path, cached: CachedDir) {
+        self.used
+            .lock()
+            .expect("index lock should not be poisoned")
+            .insert(key.to_path_buf(), cached);
+    }
+
+    /// Write the index back to disk when it changed.
+    ///
+    /// Directories not needed in this run are kept for other patterns and later runs.
+    /// With a `complete` walk directories which are gone are dropped from the index:
+    /// the ones no longer listed by their parent as read in this run and the ones which no longer exist.
+    /// An incomplete walk did not see every directory so nothing is dropped then.
+    pub fn save(self, complete: bool) -> std::io::Result<()> {
+        let Some(file) = self.file else {
+            return Ok(());
+        };
+        let used = self
+            .used
+            .into_inner()
+            .expect("index lock should not be poisoned");
+        let known = self.known.len();
+        let mut dirs = self.known;
+        dirs.extend(used.iter().map(|(key, dir)| (key.clone(), dir.clone())));
+        if complete {
+            dirs.retain(|key, _| {
+                used.contains_key(key)
+                    || (is_listed(&used, key) && self.base_dir.join(key).is_dir())
+            });
+        }
+        let unchanged = !self.changed.load(Ordering::Relaxed) && dirs.len() == known;
+        if unchanged {
+            return Ok(());
+        }
+        crate::cache::write(&file, &serialize(&dirs))
+    }
+}
+
+/// Whether the parent of `key` still lists it as a directory when it was read in this run.
+fn is_listed(used: &HashMap<PathBuf, CachedDir>, key: &Path) -> bool {
+    let (Some(parent), Some(name)) = (key.parent(), key.file_name()) else {
+        return true;
+    };
+    used.get(parent).map_or(true, |parent| {
+        parent
+            .entries
+            .iter()
+            .any(|entry| entry.name == name && entry.kind == EntryKind::Directory)
+    })
+}
+
+fn read_entries(dir: &Path) -> std::io::Result<Arc<[Entry]>> {
+    let entries = dir
+        .read_dir()?
+        .filter_map(Result::ok)
+        .map(|entry| Entry {
+            kind: EntryKind::of(&entry.path()),
+            name: entry.file_name(),
+        })
+        .collect();
+    Ok(entries)
+}
+
+/// `$XDG_CACHE_HOME/project-below/index-<hash of the canonical base dir>`
+fn index_file(base_dir: &Path) -> Option<PathBuf> {
+    let base_dir = base_dir.canonicalize().ok()?;
+    crate::cache::file("index", &[base_dir.as_os_str()])
+}
+
+/// Per directory its path, mtime seconds, mtime nanoseconds, amount of entries and every entry as its kind followed by its name.
+fn serialize(dirs: &HashMap<PathBuf, CachedDir>) -> String {
+    let mut records = Records::new(VERSION);
+    for (path, dir) in dirs {
+        let Some(path) = path.to_str() else {
+            continue;
+        };
+        let mtime = dir
+            .mtime
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        records.push(path);
+        records.push(&mtime.as_secs().to_string());
+        records.push(&mtime.subsec_nanos().to_string());
+        records.push(&dir.entries.len().to_string());
+        for entry in dir.entries.iter() {
+            records.push_tagged(entry.kind, &entry.name.to_string_lossy());
+        }
+    }
+    records.as_str().to_owned()
+}
+
+fn parse(content: &str) -> Option<HashMap<PathBuf, CachedDir>> {
+    let mut records = crate::cache::records(content, VERSION)?;
+    let mut dirs = HashMap::new();
+    while let Some(path) = records.next() {
+        // An empty path means the file is corrupt
+        if path.is_empty() {
+            return None;
+        }
+        let secs = records.next()?.parse().ok()?;
+        let nanos = records.next()?.parse().ok()?;
+        let amount = records.next()?.parse::<usize>().ok()?;
+        let entries = (0..amount)
+            .map(|_| {
+                let (kind, name) = crate::cache::tagged(records.next()?)?;
+                Some(Entry {
+                    kind,
+                    name: name.into(),
+                })
+            })
+            .collect::<Option<_>>()?;
+        let mtime = SystemTime::UNIX_EPOCH + Duration::new(secs, nanos);
+        dirs.insert(path.into(), CachedDir { mtime, entries });
+    }
+    Some(dirs)
+}
+
+#[test]
+fn serialize_roundtrip() {
+    let mtime = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 42);
+    let entries = vec![
+        Entry {
+            name: "Cargo.toml".into(),
+            kind: EntryKind::File,
+        },
+        Entry {
+            name: "src".into(),
+            kind: EntryKind::Directory,
+        },
+    ];
+    let mut dirs = HashMap::new();
+    dirs.insert(
+        PathBuf::from("some/project"),
+        CachedDir {
+            mtime,
+            entries: entries.into(),
+        },
+    );
+    let parsed = parse(&serialize(&dirs)).unwrap();
+    let dir = &parsed[Path::new("some/project")];
+    assert_eq!(dir.mtime, mtime);
+    assert_eq!(dir.entries.len(), 2);
+    assert_eq!(dir.entries[0].name, "Cargo.toml");
+    assert_eq!(dir.entries[0].kind, EntryKind::File);
+    assert_eq!(dir.entries[1].name, "src");
+    assert_eq!(dir.entries[1].kind, EntryKind::Directory);
+}
+
+#[test]
+fn parse_rejects_empty_paths() {
+    assert!(parse("project-below-index-1\0").is_some());
+    assert!(parse("project-below-index-1\0\0").is_none());
+    assert!(parse("project-below-index-1\0\0some/dir\0").is_none());
+}
+
+#[test]
+fn parse_ignores_other_versions() {
+    assert!(parse("project-below-index-0\0").is_none());
+}
+
+#[test]
+fn is_listed_by_the_parent() {
+    let mut used = HashMap::new();
+    used.insert(
+        PathBuf::from("project"),
+        CachedDir {
+            mtime: SystemTime::UNIX_EPOCH,
+            entries: vec![
+                Entry {
+                    name: "src".into(),
+                    kind: EntryKind::Directory,
+                },
+                Entry {
+                    name: "README.md".into(),
+                    kind: EntryKind::File,
+                },
+            ]
+            .into(),
+        },
+    );
+    assert!(is_listed(&used, Path::new("project/src")));
+    assert!(!is_listed(&used, Path::new("project/README.md")));
+    assert!(!is_listed(&used, Path::new("project/removed")));
+    // The parent was not read in this run
+    assert!(is_listed(&used, Path::new("other/src")));
+}
@@ -0,0 +1,111 @@
+use std::process::ExitStatus;
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, OnceLock};
+
+use crossbeam_channel::Sender;
+
+use crate::command::Running;
+
+/// Handle SIGINT and SIGTERM while commands are running.
+///
+/// The first signal sets `stop` so no further commands are started, notifies `stopping` and passes the signal on to the running commands.
+/// A second signal kills all running commands and quits immediately.
+///
+/// Returns the first received signal once there is one.
+#[cfg(unix)]
+pub fn handle(
+    stop: Arc<AtomicBool>,
+    stopping: Sender<()>,
+    running: Arc<Running>,
+) -> Arc<OnceLock<i32>> {
+    use rustix::process::Signal;
+    use signal_hook::iterator::exfiltrator::WithOrigin;
+    use signal_hook::iterator::SignalsInfo;
+    use signal_hook::low_level::siginfo::Cause;
+    use std::sync::atomic::Ordering;
+    let mut signals =
+        SignalsInfo::<WithOrigin>::new([signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM])
+            .expect("should be able to register signal handlers");
+    let received = Arc::new(OnceLock::new());
+    let first = Arc::clone(&received);
+    std::thread::Builder::new()
+        .name("signals".to_owned())
+        .spawn(move || {
+            for origin in signals.forever() {
+                let signal = origin.signal;
+                if first.set(signal).is_ok() {
+                    stop.store(true, Ordering::Relaxed);
+                    _ = stopping.try_send(());
+                    if let Some(signal) = Signal::from_named_raw(signal) {
+                        // Like Ctrl+C the kernel sends signals of the terminal to its whole foreground process group
+                        let from_terminal = matches!(origin.cause, Cause::Kernel);
+                        running.forward(signal, from_terminal);
+                    }
+                    eprintln!("project-below: {} received, waiting for the running commands. Send again to kill them.", name(signal));
+                } else {
+                    eprintln!("project-below: {} received again, killing the running commands", name(signal));
+                    running.terminate_all(true);
+                    std::process::exit(exit_code(signal).into());
+                }
+            }
+        })
+        .expect("failed to spawn thread");
+    received
+}
+
+/// Without signals the default behaviour of quitting immediately remains.
+#[cfg(not(unix))]
+pub fn handle(
+    _stop: Arc<AtomicBool>,
+    _stopping: Sender<()>,
+    _running: Arc<Running>,
+) -> Arc<OnceLock<i32>> {
+    Arc::default()
+}
+
+#[cfg(unix)]
+pub fn name(signal: i32) -> &'static str {
+    signal_hook::low_level::signal_name(signal).unwrap_or("signal")
+}
+
+#[cfg(not(unix))]
+pub const fn name(_signal: i32) -> &'static str {
+    "signal"
+}
+
+/// Whether the command was ended by the `signal`. Shells report it as their exit code instead.
+#[cfg(unix)]
+pub fn killed_by(status: ExitStatus, signal: i32) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal() == Some(signal) || status.code() == Some(i32::from(exit_code(signal)))
+}
+
+#[cfg(not(unix))]
+pub const fn killed_by(_status: ExitStatus, _signal: i32) -> bool {
+    false
+}
+
+/// Shells exit with 128 + the number of the signal.
+pub fn exit_code(signal: i32) -> u8 {
+    u8::try_from(128 + signal).unwrap_or(u8::MAX)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    #[test]
+    fn names() {
+        assert_eq!(name(2), "SIGINT");
+        assert_eq!(name(15), "SIGTERM");
+    }
+
+    #[test]
+    fn killed_by_signal_or_shell() {
+        assert!(killed_by(ExitStatus::from_raw(15), 15));
+        assert!(killed_by(ExitStatus::from_raw(143 << 8), 15));
+        assert!(!killed_by(ExitStatus::from_raw(1 << 8), 15));
+        assert!(!killed_by(ExitStatus::from_raw(9), 15));
+    }
+}
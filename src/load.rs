@@ -0,0 +1,166 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How often the load is checked again while it is too high.
+const INTERVAL: Duration = Duration::from_millis(500);
+
+/// Limits of the system load below which new commands are started.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Thresholds {
+    /// 1 minute load average
+    pub load: Option<f64>,
+    /// Percentage of `some` tasks waiting in the last 10 seconds
+    pub cpu_pressure: Option<f64>,
+    pub memory_pressure: Option<f64>,
+}
+
+impl Thresholds {
+    const fn is_empty(&self) -> bool {
+        self.load.is_none() && self.cpu_pressure.is_none() && self.memory_pressure.is_none()
+    }
+
+    /// Read every configured value once to ensure they are available.
+    ///
+    /// Unlike [`Self::below`] this does not stop at the first value above its threshold.
+    pub fn check(&self) -> io::Result<()> {
+        if self.load.is_some() {
+            load_average()?;
+        }
+        if self.cpu_pressure.is_some() {
+            pressure("cpu")?;
+        }
+        if self.memory_pressure.is_some() {
+            pressure("memory")?;
+        }
+        Ok(())
+    }
+
+    /// Whether the system is below all thresholds.
+    pub fn below(&self) -> io::Result<bool> {
+        if let Some(max) = self.load {
+            if load_average()? >= max {
+                return Ok(false);
+            }
+        }
+        if let Some(max) = self.cpu_pressure {
+            if pressure("cpu")? >= max {
+                return Ok(false);
+            }
+        }
+        if let Some(max) = self.memory_pressure {
+            if pressure("memory")? >= max {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Decides when the next command of the `commandpool` may start.
+pub struct Scheduler {
+    thresholds: Thresholds,
+    stop: Arc<AtomicBool>,
+    /// Only one waiting command checks the load at a time
+    gate: Mutex<()>,
+    running: AtomicUsize,
+}
+
+/// A started command which counts as running until it is dropped.
+pub struct Slot<'a>(&'a AtomicUsize);
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Scheduler {
+    pub const fn new(thresholds: Thresholds, stop: Arc<AtomicBool>) -> Self {
+        Self {
+            thresholds,
+            stop,
+            gate: Mutex::new(()),
+            running: AtomicUsize::new(0),
+        }
+    }
+
+    /// Wait until the system load allows another command.
+    ///
+    /// Without any other running command it starts right away so the run always makes progress.
+    /// Waiting ends early on `stop` as the command will not run anyway.
+    pub fn start(&self) -> Slot<'_> {
+        let _gate = (!self.thresholds.is_empty()).then(|| {
+            let gate = self
+                .gate
+                .lock()
+                .expect("scheduler lock should not be poisoned");
+            while self.running.load(Ordering::Relaxed) > 0
+                && !self.stop.load(Ordering::Relaxed)
+                // Values which can not be read anymore do not block the commands
+                && !self.thresholds.below().unwrap_or(true)
+            {
+                std::thread::sleep(INTERVAL);
+            }
+            gate
+        });
+        self.running.fetch_add(1, Ordering::Relaxed);
+        Slot(&self.running)
+    }
+}
+
+fn load_average() -> io::Result<f64> {
+    let content = read("/proc/loadavg")?;
+    parse_load_average(&content).ok_or_else(|| invalid("/proc/loadavg"))
+}
+
+fn pressure(resource: &str) -> io::Result<f64> {
+    let file = format!("/proc/pressure/{resource}");
+    let content = read(&file)?;
+    parse_pressure(&content).ok_or_else(|| invalid(&file))
+}
+
+fn read(file: &str) -> io::Result<String> {
+    std::fs::read_to_string(file)
+        .map_err(|err| io::Error::new(err.kind(), format!("failed to read {file}: {err}")))
+}
+
+fn invalid(file: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected content of {file}"),
+    )
+}
+
+/// `0.52 0.58 0.59 1/1234 5678` starts with the 1 minute load average.
+fn parse_load_average(content: &str) -> Option<f64> {
+    content.split_whitespace().next()?.parse().ok()
+}
+
+/// `some avg10=1.23 avg60=0.50 avg300=0.10 total=12345` followed by a `full` line.
+fn parse_pressure(content: &str) -> Option<f64> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("some "))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+#[test]
+fn parse_load_average_works() {
+    assert_eq!(
+        parse_load_average("0.52 0.58 0.59 1/1234 5678\n"),
+        Some(0.52)
+    );
+    assert_eq!(parse_load_average(""), None);
+}
+
+#[test]
+fn parse_pressure_works() {
+    let content = "some avg10=1.23 avg60=0.50 avg300=0.10 total=12345\nfull avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+    assert_eq!(parse_pressure(content), Some(1.23));
+    assert_eq!(parse_pressure("full avg10=0.00\n"), None);
+}
@@ -1,83 +1,637 @@
+use std::ffi::OsString;
+use std::io::Write;
 use std::num::NonZeroUsize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::{ExitCode, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread::JoinHandle;
+use std::time::Instant;
 
 use crossbeam_channel::Receiver;
 
 use crate::check_dir_is_project::Pattern;
-use crate::cli::CommandOutput;
+use crate::cli::{CommandOutput, FailFast};
 
+mod batch;
 mod byte_lines;
+mod cache;
 mod check_dir_is_project;
 mod cli;
 mod command;
+mod confirm;
+mod dotenv;
+mod filter;
 mod harness;
+mod index;
+mod interrupt;
+mod load;
 mod path_style;
+mod placeholder;
+mod project;
+#[cfg(unix)]
+mod pty;
+mod resources;
+mod results;
+mod retry;
+mod shell;
 mod shortened_path;
+mod sort;
+mod state;
 mod took;
+#[cfg(unix)]
+mod tty;
 mod walk;
+mod walk_error;
+mod watch;
 
-fn main() {
+fn main() -> ExitCode {
     let matches = cli::Cli::get();
 
-    let threads = matches.threads();
-    let patterns = Pattern::many(matches.directory, matches.file);
+    let patterns = Pattern::many(matches.directory.clone(), matches.file.clone());
+    let stop = Arc::new(AtomicBool::new(false));
+    let confirm = match matches.confirm.then(confirm::Prompt::open).transpose() {
+        Ok(confirm) => confirm,
+        Err(err) => {
+            eprintln!("project-below: failed to open the terminal for --confirm: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let resources = resources::Inherited {
+        nice: matches.nice,
+        ionice: matches.ionice,
+        cpu_affinity: &matches.cpu_affinity,
+    };
+    if let Err(err) = resources.apply() {
+        eprintln!("project-below: failed to apply the resource controls: {err}");
+        return ExitCode::FAILURE;
+    }
+    if let Err(err) = limits(&matches).check() {
+        eprintln!("project-below: failed to apply the resource limits: {err}");
+        return ExitCode::FAILURE;
+    }
+    if let Err(err) = load(&matches).check() {
+        eprintln!("project-below: failed to read the system load for --max-load or the pressure limits: {err}");
+        return ExitCode::FAILURE;
+    }
+    let stdin = match command::Stdin::new(matches.stdin.as_ref(), matches.output) {
+        Ok(stdin) => stdin,
+        Err(err) => {
+            eprintln!("project-below: failed to prepare the input for the commands: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (rx, walk_errors) = walk(&matches, &patterns, &stop);
+    let activity = Arc::new(watch::Activity::default());
+    let rx = if matches.watch {
+        let watched = watch::watch(
+            matches.base_dir.clone(),
+            patterns.clone(),
+            matches.hidden,
+            matches.recursive,
+            !matches.command.is_empty(),
+            rx,
+            Arc::clone(&activity),
+        );
+        match watched {
+            Ok(rx) => rx,
+            Err(err) => {
+                eprintln!(
+                    "project-below: failed to watch {}: {err}",
+                    matches.base_dir.display()
+                );
+                if matches!(err.kind, notify::ErrorKind::MaxFilesWatch) {
+                    eprintln!("project-below: the limit can be raised with the sysctl fs.inotify.max_user_watches");
+                }
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        rx
+    };
 
-    let rx = walk::walk(
+    let path_style = path_style::PathStyle::new(matches.path_style, matches.base_dir.clone());
+    let exit_code = if matches.command.is_empty() {
+        list(&matches, &rx, &path_style);
+        0
+    } else if matches.batch {
+        let results = batch(&matches, &rx, &stdin);
+        let codes = results.iter().map(ExitStatus::code).collect::<Vec<_>>();
+        results::exit_code(matches.exit_code, &codes)
+    } else {
+        let (results, signal) = execute(
+            &matches, &patterns, &rx, path_style, &stdin, confirm, &stop, &activity,
+        );
+        let codes = results
+            .into_iter()
+            .map(|(_, status)| status.code())
+            .collect::<Vec<_>>();
+        signal.map_or_else(
+            || results::exit_code(matches.exit_code, &codes),
+            interrupt::exit_code,
+        )
+    };
+
+    let walk_errors = walk_errors
+        .join()
+        .expect("walk error thread should not panic");
+    if !matches.no_warnings {
+        walk_errors.print();
+    }
+    if exit_code != 0 {
+        ExitCode::from(exit_code)
+    } else if walk_errors.failed(matches.strict) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Walk the `--base-dir` for projects and pass them through `--filter-exec` and `--sort`.
+fn walk(
+    matches: &cli::Cli,
+    patterns: &[Pattern],
+    stop: &Arc<AtomicBool>,
+) -> (Receiver<PathBuf>, JoinHandle<walk_error::Summary>) {
+    let index = if matches.cache {
+        index::Index::load(&matches.base_dir, matches.refresh)
+    } else {
+        index::Index::disabled(&matches.base_dir)
+    };
+    let (rx, walk_errors) = walk::walk(
         &matches.base_dir,
-        patterns,
+        patterns.to_vec(),
         matches.hidden,
         matches.recursive,
+        index,
+        matches.walk_threads,
+        // With sorting or filtering all results are needed to find the first ones
+        matches
+            .max_results
+            .filter(|_| matches.sort.is_none() && matches.filter_exec.is_none()),
+        Arc::clone(stop),
     );
+    let walk_errors = walk_error::report(walk_errors, !matches.no_warnings);
+    if matches.dry_run && matches.filter_exec.is_some() {
+        eprintln!(
+            "project-below: --filter-exec still runs its command in every project to select them"
+        );
+    }
+    let rx = match &matches.filter_exec {
+        Some(command) => filter(matches, command, patterns, rx),
+        None => rx,
+    };
+    let rx = match matches.sort {
+        Some(by) => sort::sort(rx, by, matches.reverse, matches.max_results),
+        None => rx,
+    };
+    (rx, walk_errors)
+}
 
-    let path_style = path_style::PathStyle::new(matches.path_style, matches.base_dir);
+fn list(matches: &cli::Cli, rx: &Receiver<PathBuf>, path_style: &path_style::PathStyle) {
+    for path in rx {
+        if matches.print0 {
+            print!("{}\0", path_style.path(&path));
+            if matches.watch {
+                _ = std::io::stdout().flush();
+            }
+        } else {
+            println!("{}", path_style.path(&path));
+        }
+    }
+}
+
+fn filter(
+    matches: &cli::Cli,
+    command: &OsString,
+    patterns: &[Pattern],
+    rx: Receiver<PathBuf>,
+) -> Receiver<PathBuf> {
+    let filter = filter::Filter {
+        command: command.clone(),
+        shell: matches.shell().unwrap_or_else(|| "sh".into()),
+        invert: matches.invert,
+        timeout: timeout(matches),
+        base_dir: matches.base_dir.clone(),
+        patterns: patterns.to_vec(),
+    };
+    let threads = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+    // With sorting the first results are only known afterwards
+    let max_results = matches.max_results.filter(|_| matches.sort.is_none());
+    filter.filter(rx, threads, max_results)
+}
 
-    if matches.command.is_empty() {
-        for path in rx {
-            if matches.print0 {
-                print!("{}\0", path_style.path(&path));
-            } else {
-                println!("{}", path_style.path(&path));
+#[allow(clippy::too_many_arguments)]
+fn execute(
+    matches: &cli::Cli,
+    patterns: &[Pattern],
+    rx: &Receiver<PathBuf>,
+    path_style: path_style::PathStyle,
+    stdin: &command::Stdin,
+    confirm: Option<confirm::Prompt>,
+    stop: &Arc<AtomicBool>,
+    activity: &watch::Activity,
+) -> (Vec<(PathBuf, ExitStatus)>, Option<i32>) {
+    let threads = matches.threads();
+    let shell = matches.shell();
+    let timeout = timeout(matches);
+    let harness = harness::Config::new(
+        path_style,
+        threads,
+        matches.line_prefix_width,
+        matches.no_header,
+        matches.result,
+        shell.is_some(),
+    );
+    let retry = retry(matches);
+    let limits = limits(matches);
+    let running = Arc::new(command::Running::default());
+    // Ends the numbering of a `--watch` which would not end on its own
+    let (stopping, stopped) = crossbeam_channel::bounded(1);
+    let signal = interrupt::handle(Arc::clone(stop), stopping.clone(), Arc::clone(&running));
+    let not_started = Mutex::new(Vec::new());
+    let interrupted = Mutex::new(Vec::new());
+    let results = results::Results::default();
+    let (state, previous) = state(matches);
+    let rx = previous.map_or_else(|| rx.clone(), |previous| previous.filter(rx.clone()));
+    let rx = confirm.map_or_else(
+        || rx.clone(),
+        |confirm| {
+            let path_style =
+                path_style::PathStyle::new(matches.path_style, matches.base_dir.clone());
+            confirm.confirm(rx.clone(), path_style)
+        },
+    );
+    let (rx, total) = numbered(rx, stopped);
+    let scheduler = load::Scheduler::new(load(matches), Arc::clone(stop));
+    commandpool(threads, &rx, &scheduler, |(index, path)| {
+        state.pending(&path);
+        if stop.load(Ordering::Relaxed) {
+            not_started.lock().unwrap().push(path);
+            return;
+        }
+        let project = project::Project::new(&path, &matches.base_dir, patterns);
+        let steps = matches
+            .steps()
+            .iter()
+            .map(|step| placeholder::expand(step, &project, shell.is_some()))
+            .collect::<Vec<_>>();
+        let env = project.env(index, total.get().copied());
+        let env_file = env_file(matches.env_file.as_deref(), &path);
+        let harness = harness.create(&path);
+        let command = |raw: &[OsString]| {
+            command::Command::new(raw, &path, shell.as_deref())
+                .envs(&env)
+                .envs(env_file.as_deref().unwrap_or_default())
+                .envs(&matches.env)
+                .stdin(stdin.clone())
+                .pty(matches.pty)
+                .limits(limits)
+                .timeout(timeout)
+                .foreground(threads.get() == 1)
+                .process_group(matches.fail_fast == Some(FailFast::Kill))
+                .track(Arc::clone(&running))
+        };
+        activity.started(&path);
+        let finished = match env_file {
+            Ok(_) => run_steps(&harness, matches, &retry, stop, &steps, command),
+            Err(finished) => {
+                harness.result(&finished, 1);
+                Some(finished)
+            }
+        };
+        activity.finished(&path);
+        let Some(finished) = finished else {
+            return;
+        };
+        if finished.cancelled {
+            not_started.lock().unwrap().push(path);
+            return;
+        }
+        if signal
+            .get()
+            .is_some_and(|signal| interrupt::killed_by(finished.status, *signal))
+        {
+            interrupted.lock().unwrap().push(path.clone());
+        } else {
+            let first_failure = matches.fail_fast.is_some()
+                && !finished.status.success()
+                && !stop.swap(true, Ordering::Relaxed);
+            if first_failure {
+                _ = stopping.try_send(());
             }
+            if first_failure && matches.fail_fast == Some(FailFast::Kill) {
+                running.terminate_all_then_kill(matches.kill_after);
+            }
+        }
+        state.finished(&path, finished.status.success());
+        results.add(path, finished.status);
+    });
+    state.save();
+
+    let results = results.into_inner();
+    let signal = signal.get().copied();
+    let (interrupted, not_started) = (sorted(interrupted), sorted(not_started));
+    harness.stopped(signal, &results, &interrupted, &not_started);
+    (results, signal)
+}
+
+fn sorted(paths: Mutex<Vec<PathBuf>>) -> Vec<PathBuf> {
+    let mut paths = paths.into_inner().unwrap();
+    paths.sort();
+    paths
+}
+
+/// The state of this run and with `--resume` or `--rerun-failed` the previous one.
+fn state(matches: &cli::Cli) -> (state::State, Option<state::Previous>) {
+    if matches.dry_run {
+        return (state::State::disabled(&matches.base_dir), None);
+    }
+    let previous = (matches.resume || matches.rerun_failed).then(|| {
+        let previous =
+            state::Previous::load(&matches.base_dir, &matches.command, matches.rerun_failed);
+        if previous.command_line.is_empty() {
+            eprintln!("project-below: there is no previous run of this command below the base dir");
+        } else {
+            eprintln!(
+                "project-below: continuing `{}`, skipping {} projects",
+                previous.command_line,
+                previous.skipped()
+            );
         }
+        previous
+    });
+    let state = state::State::new(&matches.base_dir, &matches.command, previous.as_ref());
+    (state, previous)
+}
+
+/// Run the command once with all the found projects as arguments.
+///
+/// It is run multiple times when there are too many arguments for the OS.
+fn batch(matches: &cli::Cli, rx: &Receiver<PathBuf>, stdin: &command::Stdin) -> Vec<ExitStatus> {
+    let paths = rx.iter().collect::<Vec<_>>();
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    let shell = matches.shell();
+    let (working_dir, arguments) = batch::arguments(matches.path_style, &matches.base_dir, &paths);
+    let arguments = if shell.is_some() {
+        arguments.iter().map(|arg| shell::quote(arg)).collect()
     } else {
-        let harness = harness::Config::new(
-            path_style,
-            threads,
-            matches.line_prefix_width,
-            matches.no_header,
-            matches.result,
-        );
-        commandpool(threads, &rx, |path| {
-            let command = command::Command::new(&matches.command, &path);
-            let harness = harness.create(&path);
-            match matches.output {
-                CommandOutput::Inherit => {
+        arguments
+    };
+    // The harness shows the batch instead of a project path
+    let harness = harness::Config::new(
+        path_style::PathStyle::BaseDir(PathBuf::new()),
+        NonZeroUsize::MIN,
+        matches.line_prefix_width,
+        matches.no_header,
+        matches.result,
+        false,
+    );
+    let (timeout, retry, limits) = (timeout(matches), retry(matches), limits(matches));
+    let stop = AtomicBool::new(false);
+    let longest_step = matches
+        .steps()
+        .into_iter()
+        .max_by_key(|step| step.iter().map(|arg| arg.len()).sum::<usize>());
+    let batches = batch::split(&longest_step.unwrap_or_default(), arguments);
+    let total = batches.len();
+    batches
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, arguments)| {
+            let steps = matches
+                .steps()
+                .iter()
+                .map(|step| [step.as_slice(), arguments.as_slice()].concat())
+                .collect::<Vec<_>>();
+            let label = PathBuf::from(format!("batch {} of {total}", index + 1));
+            let harness = harness.create(&label);
+            let command = |raw: &[OsString]| {
+                command::Command::new(raw, &working_dir, shell.as_deref())
+                    .envs(&matches.env)
+                    .stdin(stdin.clone())
+                    .pty(matches.pty)
+                    .limits(limits)
+                    .timeout(timeout)
+                    .foreground(true)
+            };
+            run_steps(&harness, matches, &retry, &stop, &steps, command)
+                .map(|finished| finished.status)
+        })
+        .collect()
+}
+
+fn timeout(matches: &cli::Cli) -> Option<command::Timeout> {
+    matches.timeout.map(|after| command::Timeout {
+        after,
+        kill_after: matches.kill_after,
+    })
+}
+
+fn retry(matches: &cli::Cli) -> retry::Retry {
+    retry::Retry {
+        retries: matches.retry,
+        delay: matches.retry_delay,
+        exit_codes: matches.retry_on.clone(),
+    }
+}
+
+const fn limits(matches: &cli::Cli) -> resources::Limits {
+    resources::Limits {
+        memory: matches.memory_limit,
+        open_files: matches.max_open_files,
+    }
+}
+
+const fn load(matches: &cli::Cli) -> load::Thresholds {
+    load::Thresholds {
+        load: matches.max_load,
+        cpu_pressure: matches.max_cpu_pressure,
+        memory_pressure: matches.max_memory_pressure,
+    }
+}
+
+/// Variables of the dotenv file `name` of the project at `path`. Projects do not need to have one.
+///
+/// The project fails without running the command when the file can not be read.
+fn env_file(name: Option<&Path>, path: &Path) -> Result<Vec<(String, String)>, command::Finished> {
+    let Some(name) = name else {
+        return Ok(Vec::new());
+    };
+    let file = path.join(name);
+    match dotenv::read(&file) {
+        Ok(vars) => Ok(vars),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(command::Finished::not_started(
+            1,
+            format!("failed to read {}: {err}", file.display()),
+        )),
+    }
+}
+
+/// Run the `steps` created by `command` one after another until one of them fails.
+///
+/// Returns the result of the last step which ran or [`None`] with `--dry-run`.
+/// A step without any arguments fails without running.
+/// It is cancelled when the first step was not started anymore after all commands were terminated.
+/// No further steps are started once `stop` is set.
+fn run_steps<F>(
+    harness: &harness::Harness,
+    matches: &cli::Cli,
+    retry: &retry::Retry,
+    stop: &AtomicBool,
+    steps: &[Vec<OsString>],
+    command: F,
+) -> Option<command::Finished>
+where
+    F: Fn(&[OsString]) -> command::Command,
+{
+    let mut finished = None;
+    for (index, raw) in steps.iter().enumerate() {
+        let harness = harness.step(index, steps.len());
+        // A `{match}` without matches leaves nothing to run
+        if raw.is_empty() {
+            let result = command::Finished::not_started(
+                127,
+                "no program is left after expanding the placeholders".to_owned(),
+            );
+            harness.result(&result, 1);
+            finished = Some(result);
+            break;
+        }
+        if matches.dry_run {
+            harness.dry_run(&command(raw), matches.output);
+            continue;
+        }
+        if index > 0 && stop.load(Ordering::Relaxed) {
+            break;
+        }
+        let result = run(&harness, matches.output, retry, stop, || command(raw));
+        // The previous step is the last one which ran
+        if result.cancelled && finished.is_some() {
+            break;
+        }
+        let success = result.status.success();
+        finished = Some(result);
+        if !success {
+            break;
+        }
+    }
+    finished
+}
+
+/// Run the command created by `command` until it succeeds or shouldn't be retried anymore.
+///
+/// No retries happen once `stop` is set.
+/// The duration of the result includes all attempts and the delays between them.
+fn run<F>(
+    harness: &harness::Harness,
+    output: CommandOutput,
+    retry: &retry::Retry,
+    stop: &AtomicBool,
+    command: F,
+) -> command::Finished
+where
+    F: Fn() -> command::Command,
+{
+    let start = Instant::now();
+    let mut attempt = 1;
+    let mut previous = None;
+    loop {
+        let command = command();
+        let (mut finished, output) = match output {
+            CommandOutput::Inherit => {
+                if attempt == 1 {
                     harness.inherit_header();
-                    let (status, took) = command.inherit();
-                    harness.result(took, status);
-                }
-                CommandOutput::LinePrefix => {
-                    let (status, took) = command.lineprefixed(&harness.line_prefix());
-                    harness.result(took, status);
-                }
-                CommandOutput::Collect => {
-                    let (output, took) = command.output();
-                    let _stdout = std::io::stdout().lock();
-                    harness.collect(&output);
-                    harness.result(took, output.status);
-                }
-                CommandOutput::Null => {
-                    let (status, took) = command.null();
-                    harness.result(took, status);
                 }
+                (command.inherit(), None)
+            }
+            CommandOutput::LinePrefix => (command.lineprefixed(&harness.line_prefix()), None),
+            CommandOutput::Collect => {
+                let (output, finished) = command.output();
+                (finished, Some(output))
             }
-        });
+            CommandOutput::Null => (command.null(), None),
+        };
+        if finished.cancelled {
+            // A cancelled retry leaves the previous attempt as the result
+            return previous.map_or(finished, |previous| {
+                harness.result(&previous, attempt - 1);
+                previous
+            });
+        }
+        let again = retry.should_retry(attempt, &finished) && !stop.load(Ordering::Relaxed);
+        if attempt > 1 {
+            finished.took = start.elapsed();
+        }
+        {
+            let _stdout = std::io::stdout().lock();
+            if let Some(output) = output {
+                harness.collect(&output);
+            }
+            if !again {
+                harness.result(&finished, attempt);
+            }
+        }
+        if !again {
+            return finished;
+        }
+        previous = Some(finished);
+        std::thread::sleep(retry.delay(attempt));
+        attempt += 1;
     }
 }
 
-fn commandpool<'scope, F>(threads: NonZeroUsize, rx: &Receiver<PathBuf>, func: F)
-where
-    F: Fn(PathBuf) + Send + Sync + 'scope,
+/// Number the paths in the order they are received starting with 1.
+///
+/// The total amount is known once all paths were received.
+///
+/// Once `stopped` receives the paths which already arrived are passed on and the receiving ends,
+/// as a `--watch` would never end on its own.
+fn numbered(
+    rx: Receiver<PathBuf>,
+    stopped: Receiver<()>,
+) -> (Receiver<(usize, PathBuf)>, Arc<OnceLock<usize>>) {
+    let (tx, numbered) = crossbeam_channel::unbounded();
+    let total = Arc::new(OnceLock::new());
+    let total_set = Arc::clone(&total);
+    std::thread::Builder::new()
+        .name("numbered".to_owned())
+        .spawn(move || {
+            let mut count = 0;
+            let mut send = |path| {
+                count += 1;
+                _ = tx.send((count, path));
+            };
+            loop {
+                crossbeam_channel::select! {
+                    recv(rx) -> path => match path {
+                        Ok(path) => send(path),
+                        Err(_) => break,
+                    },
+                    recv(stopped) -> _ => {
+                        rx.try_iter().for_each(&mut send);
+                        break;
+                    }
+                }
+            }
+            _ = total_set.set(count);
+        })
+        .expect("failed to spawn thread");
+    (numbered, total)
+}
+
+fn commandpool<'scope, T, F>(
+    threads: NonZeroUsize,
+    rx: &Receiver<T>,
+    scheduler: &load::Scheduler,
+    func: F,
+) where
+    T: Send,
+    F: Fn(T) + Send + Sync + 'scope,
 {
     std::thread::scope(|scope| {
         for _ in 1..threads.get() {
@@ -85,14 +639,16 @@ where
             std::thread::Builder::new()
                 .name("commandpool".to_owned())
                 .spawn_scoped(scope, || {
-                    for path in rx {
-                        func(path);
+                    for item in rx {
+                        let _slot = scheduler.start();
+                        func(item);
                     }
                 })
                 .expect("failed to spawn thread");
         }
-        for path in rx {
-            func(path);
+        for item in rx {
+            let _slot = scheduler.start();
+            func(item);
         }
     });
 }
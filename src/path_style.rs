@@ -1,3 +1,4 @@
+use std::ffi::OsStr;
 use std::fmt;
 use std::path::{Path, PathBuf};
 
@@ -34,6 +35,31 @@ pub struct DPath<'a> {
     path: &'a Path,
     kind: &'a PathStyle,
 }
+
+impl DPath<'_> {
+    /// The path quoted for a shell with its exact bytes instead of the lossy display.
+    pub fn quoted(&self) -> String {
+        let path = self.path;
+        let raw = match &self.kind {
+            PathStyle::BaseDir(base) => Some(path.strip_prefix(base).unwrap_or(path).to_path_buf()),
+            PathStyle::Canonical => {
+                Some(path.canonicalize().unwrap_or_else(|_| path.to_path_buf()))
+            }
+            PathStyle::Dirname => path.file_name().map(PathBuf::from),
+            PathStyle::WorkingDir(pwd) => {
+                Some(pathdiff::diff_paths(path, pwd).unwrap_or_else(|| path.to_path_buf()))
+            }
+            // Shortened paths are only meant for display
+            PathStyle::Short(_) => None,
+        };
+        let quoted = raw.map_or_else(
+            || crate::shell::quote_display(OsStr::new(&self.to_string())),
+            |raw| crate::shell::quote_display(raw.as_os_str()),
+        );
+        // The quoting escapes everything which is not valid UTF-8
+        quoted.to_string_lossy().into_owned()
+    }
+}
 impl fmt::Display for DPath<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         let path = self.path;
@@ -0,0 +1,180 @@
+use std::ffi::OsString;
+
+use crate::project::Project;
+use crate::shell;
+
+/// Placeholder which is replaced by one argument per matched file when used as a whole argument.
+const MATCH: &str = "{match}";
+
+const PLACEHOLDERS: [&str; 5] = ["{path}", "{name}", "{rel}", "{parent}", MATCH];
+
+/// Replace the placeholders in the command arguments with values of the project.
+///
+/// `{path}` absolute path, `{name}` directory name, `{rel}` path relative to the `--base-dir`, `{parent}` absolute parent directory and `{match}` the files matching the patterns.
+/// Like with `fd` double braces `{{` and `}}` are literal braces, so `{{name}}` results in the literal `{name}`.
+///
+/// With `quote` the values are quoted for a shell as the arguments are joined to a command line later on.
+pub fn expand(raw: &[OsString], project: &Project, quote: bool) -> Vec<OsString> {
+    let quoted = |value: OsString| {
+        if quote {
+            shell::quote(&value)
+        } else {
+            value
+        }
+    };
+    let mut result = Vec::with_capacity(raw.len());
+    for arg in raw {
+        if arg == MATCH {
+            result.extend(project.matches().iter().map(OsString::from).map(quoted));
+            continue;
+        }
+        match arg.to_str() {
+            Some(arg) if arg.contains(['{', '}']) => {
+                result.push(expand_arg(arg, |placeholder| {
+                    quoted(value(placeholder, project))
+                }));
+            }
+            _ => result.push(arg.clone()),
+        }
+    }
+    result
+}
+
+fn value(placeholder: &str, project: &Project) -> OsString {
+    match placeholder {
+        "{path}" => project.absolute().into(),
+        "{name}" => project.name().into(),
+        "{rel}" => project.relative().into(),
+        "{parent}" => project.parent().into(),
+        MATCH => {
+            let mut joined = OsString::new();
+            for (index, path) in project.matches().iter().enumerate() {
+                if index > 0 {
+                    joined.push(" ");
+                }
+                joined.push(path);
+            }
+            joined
+        }
+        _ => unreachable!("only known placeholders are expanded"),
+    }
+}
+
+fn expand_arg<F>(arg: &str, value: F) -> OsString
+where
+    F: Fn(&str) -> OsString,
+{
+    let mut result = OsString::new();
+    let mut rest = arg;
+    while let Some(start) = rest.find(['{', '}']) {
+        result.push(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("{{").or_else(|| rest.strip_prefix("}}")) {
+            result.push(&rest[..1]);
+            rest = after;
+        } else if let Some(placeholder) = PLACEHOLDERS
+            .iter()
+            .find(|placeholder| rest.starts_with(**placeholder))
+        {
+            result.push(value(placeholder));
+            rest = &rest[placeholder.len()..];
+        } else {
+            result.push(&rest[..1]);
+            rest = &rest[1..];
+        }
+    }
+    result.push(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::path::{Path, PathBuf};
+
+    use super::*;
+    use crate::check_dir_is_project::Pattern;
+
+    #[track_caller]
+    fn case(input: &str, expected: &str) {
+        let actual = expand_arg(input, |placeholder| {
+            OsStr::new(placeholder.trim_matches(['{', '}'])).to_ascii_uppercase()
+        });
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn without_placeholder() {
+        case("foo", "foo");
+    }
+
+    #[test]
+    fn whole_argument() {
+        case("{name}", "NAME");
+    }
+
+    #[test]
+    fn within_argument() {
+        case("/backup/{name}.tgz", "/backup/NAME.tgz");
+    }
+
+    #[test]
+    fn multiple() {
+        case("{parent}/{name}", "PARENT/NAME");
+    }
+
+    #[test]
+    fn escaped() {
+        case("{{name}}", "{name}");
+        case("{{name}}/{name}", "{name}/NAME");
+        case("{{{name}}}", "{NAME}");
+        case("{{", "{");
+        case("}}", "}");
+    }
+
+    #[test]
+    fn unknown_is_kept() {
+        case("{}", "{}");
+        case("{foo}", "{foo}");
+        case(r#"{"a":{"b":1}"#, r#"{"a":{"b":1}"#);
+    }
+
+    #[track_caller]
+    fn expand_in_repo(directory: &[&str], file: &[&str], raw: &[&str]) -> Vec<OsString> {
+        let patterns = Pattern::many(
+            directory.iter().map(PathBuf::from).collect(),
+            file.iter().map(PathBuf::from).collect(),
+        );
+        let project = Project::new(Path::new("."), Path::new("."), &patterns);
+        let raw = raw.iter().map(OsString::from).collect::<Vec<_>>();
+        expand(&raw, &project, false)
+    }
+
+    #[test]
+    fn match_is_one_argument_per_file() {
+        let mut actual = expand_in_repo(&["src"], &["Cargo.toml"], &["ls", "{match}", "-l"]);
+        assert_eq!(actual.remove(0), "ls");
+        assert_eq!(actual.pop().unwrap(), "-l");
+        actual.sort();
+        assert_eq!(actual, ["Cargo.toml", "src"]);
+    }
+
+    #[test]
+    fn match_within_argument_is_joined() {
+        let actual = expand_in_repo(&[], &["Cargo.toml"], &["--file={match}"]);
+        assert_eq!(actual, ["--file=Cargo.toml"]);
+    }
+
+    #[test]
+    fn match_without_matches_is_removed() {
+        let actual = expand_in_repo(&[], &["does-not-exist"], &["{match}"]);
+        assert!(actual.is_empty());
+    }
+
+    #[test]
+    fn escaped_braces() {
+        case(r#"{{"a":{{"b":1}}}}"#, r#"{"a":{"b":1}}"#);
+        case("{{{{name}}}}", "{{name}}");
+    }
+}
@@ -0,0 +1,91 @@
+use std::cell::OnceCell;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::check_dir_is_project::{project_matches, Pattern};
+use crate::index::Index;
+
+/// A found project with information about it, for example for placeholders in the command.
+pub struct Project<'a> {
+    path: &'a Path,
+    base_dir: &'a Path,
+    patterns: &'a [Pattern],
+    /// The pattern search is only done once for all placeholders and environment variables
+    matches: OnceCell<Vec<PathBuf>>,
+}
+
+impl<'a> Project<'a> {
+    pub const fn new(path: &'a Path, base_dir: &'a Path, patterns: &'a [Pattern]) -> Self {
+        Self {
+            path,
+            base_dir,
+            patterns,
+            matches: OnceCell::new(),
+        }
+    }
+
+    pub fn absolute(&self) -> PathBuf {
+        self.path
+            .canonicalize()
+            .unwrap_or_else(|_| self.path.to_path_buf())
+    }
+
+    pub fn name(&self) -> &Path {
+        self.path.file_name().map_or(self.path, Path::new)
+    }
+
+    pub fn relative(&self) -> &Path {
+        self.path.strip_prefix(self.base_dir).unwrap_or(self.path)
+    }
+
+    pub fn parent(&self) -> PathBuf {
+        let absolute = self.absolute();
+        absolute
+            .parent()
+            .map_or_else(|| absolute.clone(), Path::to_path_buf)
+    }
+
+    /// Environment variables describing the project for the command.
+    ///
+    /// `index` is the number of the project starting with 1.
+    /// The `total` amount of projects is only known once the directory walk finished.
+    pub fn env(&self, index: usize, total: Option<usize>) -> Vec<(&'static str, OsString)> {
+        let base_dir = self
+            .base_dir
+            .canonicalize()
+            .unwrap_or_else(|_| self.base_dir.to_path_buf());
+        let mut matches = OsString::new();
+        for (index, path) in self.matches().iter().enumerate() {
+            if index > 0 {
+                matches.push("\n");
+            }
+            matches.push(path);
+        }
+        let mut env = vec![
+            ("PROJECT_BELOW_PATH", self.absolute().into()),
+            ("PROJECT_BELOW_NAME", self.name().into()),
+            ("PROJECT_BELOW_BASE_DIR", base_dir.into()),
+            ("PROJECT_BELOW_INDEX", index.to_string().into()),
+            ("PROJECT_BELOW_MATCHES", matches),
+        ];
+        if let Some(total) = total {
+            env.push(("PROJECT_BELOW_TOTAL", total.to_string().into()));
+        }
+        env
+    }
+
+    /// The files and directories which matched the `--directory` and `--file` patterns relative to the project.
+    pub fn matches(&self) -> &[PathBuf] {
+        self.matches.get_or_init(|| {
+            let index = Index::disabled(self.base_dir);
+            project_matches(self.patterns, self.path, &index, &mut Vec::new())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|path| {
+                    path.strip_prefix(self.path)
+                        .map_or_else(|_| path.clone(), Path::to_path_buf)
+                })
+                .collect()
+        })
+    }
+}
@@ -0,0 +1,74 @@
+use std::fs::File;
+use std::io::Read;
+use std::process::Stdio;
+
+use rustix::fs::{Mode, OFlags};
+use rustix::pty::{grantpt, openpt, ptsname, unlockpt, OpenptFlags};
+use rustix::termios::{
+    tcgetattr, tcgetwinsize, tcsetattr, tcsetwinsize, OptionalActions, OutputModes,
+};
+
+/// A pseudo-terminal for the output of a command so it keeps its colours and interactive output.
+pub struct Pty {
+    master: File,
+    slave: File,
+}
+
+impl Pty {
+    /// The size is taken from the terminal of project-below with `reserved_columns` less for a prefix.
+    pub fn open(reserved_columns: usize) -> std::io::Result<Self> {
+        let master = openpt(OpenptFlags::RDWR | OpenptFlags::NOCTTY | OpenptFlags::CLOEXEC)?;
+        grantpt(&master)?;
+        unlockpt(&master)?;
+        let name = ptsname(&master, Vec::new())?;
+        let slave = rustix::fs::open(
+            name.as_c_str(),
+            OFlags::RDWR | OFlags::NOCTTY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )?;
+
+        // Lines end with \n instead of \r\n like in pipes
+        let mut termios = tcgetattr(&slave)?;
+        termios.output_modes.remove(OutputModes::ONLCR);
+        tcsetattr(&slave, OptionalActions::Now, &termios)?;
+
+        if let Ok(mut size) = tcgetwinsize(std::io::stdout()) {
+            let reserved = u16::try_from(reserved_columns).unwrap_or(u16::MAX);
+            size.ws_col = size.ws_col.saturating_sub(reserved).max(20);
+            tcsetwinsize(&slave, size)?;
+        }
+
+        Ok(Self {
+            master: master.into(),
+            slave: slave.into(),
+        })
+    }
+
+    /// Stdio for both stdout and stderr of the command.
+    pub fn stdio(&self) -> std::io::Result<(Stdio, Stdio)> {
+        Ok((
+            self.slave.try_clone()?.into(),
+            self.slave.try_clone()?.into(),
+        ))
+    }
+
+    /// Read the output of the command until it and all of its child processes closed the terminal.
+    ///
+    /// The own end of the command has to be dropped for this to end.
+    pub fn reader(self) -> PtyReader {
+        drop(self.slave);
+        PtyReader(self.master)
+    }
+}
+
+pub struct PtyReader(File);
+
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            // Linux reports EIO once all slaves are closed instead of EOF
+            Err(err) if err.raw_os_error() == Some(rustix::io::Errno::IO.raw_os_error()) => Ok(0),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::io;
+use std::ops::RangeInclusive;
+use std::process::Command;
+
+use crate::cli::IoniceClass;
+
+/// Resource controls which are applied to project-below itself in order to be inherited by all the commands.
+///
+/// Setting them only for the command before it starts would require unsafe code.
+pub struct Inherited<'a> {
+    pub nice: Option<i32>,
+    pub ionice: Option<IoniceClass>,
+    pub cpu_affinity: &'a [RangeInclusive<usize>],
+}
+
+impl Inherited<'_> {
+    pub fn apply(&self) -> io::Result<()> {
+        if let Some(nice) = self.nice {
+            set_nice(nice)?;
+        }
+        if let Some(class) = self.ionice {
+            set_ionice(class)?;
+        }
+        if !self.cpu_affinity.is_empty() {
+            set_cpu_affinity(self.cpu_affinity)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn set_nice(nice: i32) -> io::Result<()> {
+    Ok(rustix::process::setpriority_process(None, nice)?)
+}
+
+#[cfg(not(unix))]
+fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(unsupported("--nice"))
+}
+
+/// There is no syscall wrapper for `ioprio_set` without unsafe code so the `ionice` tool is used.
+#[cfg(target_os = "linux")]
+fn set_ionice(class: IoniceClass) -> io::Result<()> {
+    let class = match class {
+        IoniceClass::Realtime => "1",
+        IoniceClass::BestEffort => "2",
+        IoniceClass::Idle => "3",
+    };
+    let status = Command::new("ionice")
+        .arg("--class")
+        .arg(class)
+        .arg("--pid")
+        .arg(std::process::id().to_string())
+        .status()
+        .map_err(|err| io::Error::new(err.kind(), format!("failed to run ionice: {err}")))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("ionice failed with {status}"),
+        ))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_ionice(_class: IoniceClass) -> io::Result<()> {
+    Err(unsupported("--ionice"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_cpu_affinity(cpus: &[RangeInclusive<usize>]) -> io::Result<()> {
+    use rustix::thread::CpuSet;
+    let mut set = CpuSet::new();
+    for cpu in cpus.iter().cloned().flatten() {
+        if cpu >= CpuSet::MAX_CPU {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("CPU {cpu} is out of range"),
+            ));
+        }
+        set.set(cpu);
+    }
+    Ok(rustix::thread::sched_setaffinity(None, &set)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_cpu_affinity(_cpus: &[RangeInclusive<usize>]) -> io::Result<()> {
+    Err(unsupported("--cpu-affinity"))
+}
+
+/// Resource limits of a single command.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Address space in bytes
+    pub memory: Option<u64>,
+    pub open_files: Option<u64>,
+}
+
+impl Limits {
+    const fn is_empty(self) -> bool {
+        self.memory.is_none() && self.open_files.is_none()
+    }
+
+    /// Whether the limits can be applied on this platform.
+    ///
+    /// They are applied by the `prlimit` tool of util-linux.
+    #[cfg(target_os = "linux")]
+    pub fn check(self) -> io::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let status = Command::new("prlimit")
+            .arg("--version")
+            .stdout(std::process::Stdio::null())
+            .status()
+            .map_err(|err| io::Error::new(err.kind(), format!("failed to run prlimit: {err}")))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("prlimit failed with {status}"),
+            ))
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn check(self) -> io::Result<()> {
+        if self.memory.is_some() {
+            return Err(unsupported("--memory-limit"));
+        }
+        if self.open_files.is_some() {
+            return Err(unsupported("--max-open-files"));
+        }
+        Ok(())
+    }
+
+    /// Run the `command` via `prlimit` so the limits are in place before it runs.
+    ///
+    /// There is no way to set them for the command before it starts without unsafe code.
+    /// The working directory and the environment variables set so far are taken over.
+    ///
+    /// The program is looked up first as `prlimit` would hide why it can not be started.
+    /// The `command` stays as it is when it can not be started.
+    #[cfg(target_os = "linux")]
+    pub fn wrap(self, command: &mut Command) -> io::Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        find_program(command)?;
+        let mut prlimit = Command::new("prlimit");
+        if let Some(memory) = self.memory {
+            prlimit.arg(format!("--as={memory}"));
+        }
+        if let Some(open_files) = self.open_files {
+            prlimit.arg(format!("--nofile={open_files}"));
+        }
+        prlimit
+            .arg("--")
+            .arg(command.get_program())
+            .args(command.get_args());
+        if let Some(dir) = command.get_current_dir() {
+            prlimit.current_dir(dir);
+        }
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => prlimit.env(key, value),
+                None => prlimit.env_remove(key),
+            };
+        }
+        *command = prlimit;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub const fn wrap(self, _command: &mut Command) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Look up the program of the `command` like it is started.
+///
+/// A program containing a slash is relative to the working directory, others are searched in the `PATH` of the command.
+#[cfg(target_os = "linux")]
+fn find_program(command: &Command) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+    let program = Path::new(command.get_program());
+    let dir = command.get_current_dir().unwrap_or_else(|| Path::new("."));
+    let candidates = if program.to_string_lossy().contains('/') {
+        vec![dir.join(program)]
+    } else {
+        let path = command
+            .get_envs()
+            .find(|(key, _)| *key == "PATH")
+            .map_or_else(
+                || std::env::var_os("PATH"),
+                |(_, value)| value.map(Into::into),
+            )
+            .unwrap_or_default();
+        std::env::split_paths(&path)
+            .map(|path| dir.join(path).join(program))
+            .collect()
+    };
+    let mut result = Err(rustix::io::Errno::NOENT.into());
+    for candidate in candidates {
+        match candidate.metadata() {
+            Ok(meta) if meta.is_file() && meta.permissions().mode() & 0o111 != 0 => return Ok(()),
+            Ok(_) => result = Err(rustix::io::Errno::ACCESS.into()),
+            Err(_) => {}
+        }
+    }
+    result
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported(argument: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{argument} is not supported on this platform"),
+    )
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_apply_to_the_command() {
+        let limits = Limits {
+            memory: Some(1024 * 1024 * 1024),
+            open_files: Some(64),
+        };
+        let mut command = Command::new("sh");
+        command.arg("-c").arg("ulimit -n && ulimit -v");
+        limits.wrap(&mut command).unwrap();
+        let output = command.output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "64\n1048576\n");
+    }
+
+    #[test]
+    fn wrap_keeps_the_command() {
+        let mut command = Command::new("sh");
+        command
+            .arg("script.sh")
+            .current_dir("/tmp")
+            .env("FOO", "bar");
+        let limits = Limits {
+            memory: None,
+            open_files: Some(64),
+        };
+        limits.wrap(&mut command).unwrap();
+        let wrapped = command;
+        assert_eq!(wrapped.get_program(), "prlimit");
+        assert_eq!(
+            wrapped.get_args().collect::<Vec<_>>(),
+            ["--nofile=64", "--", "sh", "script.sh"]
+        );
+        assert_eq!(
+            wrapped.get_current_dir(),
+            Some(std::path::Path::new("/tmp"))
+        );
+        assert_eq!(
+            wrapped.get_envs().collect::<Vec<_>>(),
+            [("FOO".as_ref(), Some("bar".as_ref()))]
+        );
+    }
+
+    #[test]
+    fn missing_program_is_not_wrapped() {
+        let limits = Limits {
+            memory: None,
+            open_files: Some(64),
+        };
+        let mut command = Command::new("project-below-does-not-exist");
+        let err = limits.wrap(&mut command).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert_eq!(command.get_program(), "project-below-does-not-exist");
+
+        let mut command = Command::new("./does-not-exist");
+        command.current_dir("/");
+        assert!(limits.wrap(&mut command).is_err());
+        let mut command = Command::new("bin/sh");
+        command.current_dir("/");
+        limits.wrap(&mut command).unwrap();
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::Mutex;
+
+use crate::cli::ExitCodeMode;
+
+/// Exit statuses of the finished commands in the order they finished.
+#[derive(Debug, Default)]
+pub struct Results(Mutex<Vec<(PathBuf, ExitStatus)>>);
+
+impl Results {
+    pub fn add(&self, path: PathBuf, status: ExitStatus) {
+        self.0
+            .lock()
+            .expect("results lock should not be poisoned")
+            .push((path, status));
+    }
+
+    pub fn into_inner(self) -> Vec<(PathBuf, ExitStatus)> {
+        self.0
+            .into_inner()
+            .expect("results lock should not be poisoned")
+    }
+}
+
+/// Combine the exit codes of the commands to a single one. Commands killed by a signal have no code.
+pub fn exit_code(mode: ExitCodeMode, codes: &[Option<i32>]) -> u8 {
+    let failed = codes.iter().filter(|code| **code != Some(0)).count();
+    match mode {
+        ExitCodeMode::Any => u8::from(failed > 0),
+        ExitCodeMode::All => u8::from(failed > 0 && failed == codes.len()),
+        ExitCodeMode::Last => match codes.last() {
+            None | Some(Some(0)) => 0,
+            // Exit codes are truncated to 8 bits by the OS anyway
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Some(Some(code)) if (*code as u8) != 0 => *code as u8,
+            Some(_) => 1,
+        },
+        ExitCodeMode::Count => u8::try_from(failed).unwrap_or(u8::MAX),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [Option<i32>; 4] = [Some(0), Some(2), None, Some(0)];
+
+    #[test]
+    fn any() {
+        assert_eq!(exit_code(ExitCodeMode::Any, &CODES), 1);
+        assert_eq!(exit_code(ExitCodeMode::Any, &[Some(0)]), 0);
+        assert_eq!(exit_code(ExitCodeMode::Any, &[]), 0);
+    }
+
+    #[test]
+    fn all() {
+        assert_eq!(exit_code(ExitCodeMode::All, &CODES), 0);
+        assert_eq!(exit_code(ExitCodeMode::All, &[Some(2), None]), 1);
+        assert_eq!(exit_code(ExitCodeMode::All, &[]), 0);
+    }
+
+    #[test]
+    fn last() {
+        assert_eq!(exit_code(ExitCodeMode::Last, &CODES), 0);
+        assert_eq!(exit_code(ExitCodeMode::Last, &[Some(0), Some(42)]), 42);
+        assert_eq!(exit_code(ExitCodeMode::Last, &[Some(0), None]), 1);
+        assert_eq!(exit_code(ExitCodeMode::Last, &[Some(256)]), 1);
+    }
+
+    #[test]
+    fn count() {
+        assert_eq!(exit_code(ExitCodeMode::Count, &CODES), 2);
+        assert_eq!(exit_code(ExitCodeMode::Count, &[Some(1); 300]), 255);
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use crate::command::Finished;
+
+pub struct Retry {
+    /// How often a failed command is run again
+    pub retries: u32,
+    /// Delay before the first retry. It doubles for each further retry.
+    pub delay: Duration,
+    /// Only retry on these exit codes. All failures are retried when empty.
+    pub exit_codes: Vec<i32>,
+}
+
+impl Retry {
+    /// `attempt` is the number of the attempt which just `finished` starting with 1.
+    pub fn should_retry(&self, attempt: u32, finished: &Finished) -> bool {
+        // A missing program will not appear by trying again
+        if attempt > self.retries
+            || finished.status.success()
+            || finished.spawn_error.is_some()
+            || finished.cancelled
+        {
+            return false;
+        }
+        if self.exit_codes.is_empty() {
+            return true;
+        }
+        finished
+            .status
+            .code()
+            .is_some_and(|code| self.exit_codes.contains(&code))
+    }
+
+    /// Exponential backoff after the `attempt` starting with 1.
+    pub const fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.saturating_sub(1));
+        self.delay.saturating_mul(factor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exited(code: u8) -> Finished {
+        Finished {
+            spawn_error: None,
+            ..Finished::not_started(code, String::new())
+        }
+    }
+
+    #[track_caller]
+    fn case(exit_codes: &[i32], attempt: u32, finished: &Finished, expected: bool) {
+        let retry = Retry {
+            retries: 2,
+            delay: Duration::ZERO,
+            exit_codes: exit_codes.to_vec(),
+        };
+        assert_eq!(retry.should_retry(attempt, finished), expected);
+    }
+
+    #[test]
+    fn success_is_not_retried() {
+        case(&[], 1, &exited(0), false);
+    }
+
+    #[test]
+    fn failure_is_retried() {
+        case(&[], 1, &exited(1), true);
+        case(&[], 2, &exited(1), true);
+    }
+
+    #[test]
+    fn attempts_are_limited() {
+        case(&[], 3, &exited(1), false);
+    }
+
+    #[test]
+    fn only_given_exit_codes_are_retried() {
+        case(&[75, 128], 1, &exited(75), true);
+        case(&[75, 128], 1, &exited(128), true);
+        case(&[75, 128], 1, &exited(1), false);
+    }
+
+    #[test]
+    fn spawn_error_is_not_retried() {
+        let finished = Finished::not_started(127, "missing".to_owned());
+        case(&[], 1, &finished, false);
+        case(&[127], 1, &finished, false);
+    }
+
+    #[test]
+    fn cancelled_is_not_retried() {
+        let finished = Finished {
+            cancelled: true,
+            ..exited(1)
+        };
+        case(&[], 1, &finished, false);
+    }
+
+    #[test]
+    fn delay_doubles() {
+        let retry = Retry {
+            retries: 5,
+            delay: Duration::from_secs(1),
+            exit_codes: Vec::new(),
+        };
+        assert_eq!(retry.delay(1), Duration::from_secs(1));
+        assert_eq!(retry.delay(2), Duration::from_secs(2));
+        assert_eq!(retry.delay(3), Duration::from_secs(4));
+        assert_eq!(retry.delay(100), Duration::from_secs(u32::MAX.into()));
+    }
+}
@@ -0,0 +1,198 @@
+use std::ffi::{OsStr, OsString};
+use std::fmt::Write as _;
+
+/// Join the arguments to a single command line for `sh -c`.
+///
+/// The arguments are joined by spaces without quoting like `ssh` does.
+/// This way operators like `&&` or `|` keep their meaning.
+pub fn join(args: &[OsString]) -> OsString {
+    let mut result = OsString::new();
+    for (index, arg) in args.iter().enumerate() {
+        if index > 0 {
+            result.push(" ");
+        }
+        result.push(arg);
+    }
+    result
+}
+
+/// Quote a single argument for a POSIX shell which runs it, when it contains anything special.
+///
+/// Single quotes keep every byte as it is, including control characters and invalid UTF-8.
+/// Every POSIX shell understands them, unlike the `$'...'` of [`quote_display`].
+pub fn quote(arg: &OsStr) -> OsString {
+    if is_safe(arg) {
+        return arg.to_os_string();
+    }
+    let mut result = vec![b'\''];
+    for byte in bytes(arg) {
+        if byte == b'\'' {
+            result.extend_from_slice(br"'\''");
+        } else {
+            result.push(byte);
+        }
+    }
+    result.push(b'\'');
+    from_bytes(result)
+}
+
+/// Quote a single argument for a POSIX shell in order to show it.
+///
+/// Arguments which are not valid UTF-8 or contain control characters use the `$'...'` quoting of bash, zsh and ksh.
+/// This way the exact bytes are kept and visible, for example to copy a path from the output.
+/// Other shells like dash do not understand it, so use [`quote`] for what a shell runs.
+pub fn quote_display(arg: &OsStr) -> OsString {
+    match arg.to_str() {
+        Some(arg) if arg.chars().any(char::is_control) => ansi_c(arg.as_bytes()).into(),
+        Some(_) => quote(arg),
+        None => ansi_c(&bytes(arg)).into(),
+    }
+}
+
+fn is_safe(arg: &OsStr) -> bool {
+    arg.to_str().is_some_and(|arg| {
+        !arg.is_empty()
+            && arg
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || "%+,-./:=@_".contains(char))
+    })
+}
+
+/// `$'...'` with escapes for the invalid UTF-8 bytes and control characters.
+fn ansi_c(mut bytes: &[u8]) -> String {
+    let mut result = String::from("$'");
+    while !bytes.is_empty() {
+        let (valid, invalid) = match std::str::from_utf8(bytes) {
+            Ok(valid) => (valid, 0),
+            Err(err) => {
+                let (valid, rest) = bytes.split_at(err.valid_up_to());
+                let valid = std::str::from_utf8(valid).expect("checked to be valid UTF-8");
+                (valid, err.error_len().unwrap_or(rest.len()))
+            }
+        };
+        for char in valid.chars() {
+            match char {
+                '\\' => result += r"\\",
+                '\'' => result += r"\'",
+                '\n' => result += r"\n",
+                '\t' => result += r"\t",
+                char if char.is_ascii_control() => {
+                    write!(result, "\\x{:02x}", u32::from(char))
+                        .expect("writing to a String should not fail");
+                }
+                char => result.push(char),
+            }
+        }
+        let rest = &bytes[valid.len()..];
+        for byte in &rest[..invalid] {
+            write!(result, "\\x{byte:02x}").expect("writing to a String should not fail");
+        }
+        bytes = &rest[invalid..];
+    }
+    result.push('\'');
+    result
+}
+
+#[cfg(unix)]
+fn bytes(arg: &OsStr) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    arg.as_bytes().to_vec()
+}
+
+/// Only Unix has arbitrary bytes in arguments which a POSIX shell can express.
+#[cfg(not(unix))]
+fn bytes(arg: &OsStr) -> Vec<u8> {
+    arg.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn from_bytes(bytes: Vec<u8>) -> OsString {
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn case(input: &str, expected: &str) {
+        assert_eq!(quote(OsStr::new(input)), expected);
+    }
+
+    #[test]
+    fn safe_is_unquoted() {
+        case("foo/bar-baz_1.2", "foo/bar-baz_1.2");
+    }
+
+    #[test]
+    fn empty() {
+        case("", "''");
+    }
+
+    #[test]
+    fn space() {
+        case("foo bar", "'foo bar'");
+    }
+
+    #[test]
+    fn single_quote() {
+        case("it's", r"'it'\''s'");
+    }
+
+    #[test]
+    fn special_chars() {
+        case("$HOME", "'$HOME'");
+        case("a&&b", "'a&&b'");
+    }
+
+    #[test]
+    fn control_chars_stay_in_single_quotes() {
+        case("a\nb", "'a\nb'");
+        case("tab\there's", "'tab\there'\\''s'");
+    }
+
+    #[test]
+    fn display_control_chars() {
+        let display = |input: &str| quote_display(OsStr::new(input));
+        assert_eq!(display("foo bar"), "'foo bar'");
+        assert_eq!(display("a\nb"), r"$'a\nb'");
+        assert_eq!(display("tab\there's"), r"$'tab\there\'s'");
+        assert_eq!(display("bell\x07"), r"$'bell\x07'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_keeps_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        let arg = OsStr::from_bytes(b"caf\xe9 \\ \xff\xfe");
+        assert_eq!(quote(arg), OsStr::from_bytes(b"'caf\xe9 \\ \xff\xfe'"));
+        assert_eq!(quote_display(arg), r"$'caf\xe9 \\ \xff\xfe'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sh_keeps_the_exact_bytes() {
+        use std::os::unix::ffi::OsStrExt;
+        let arg = OsStr::from_bytes(b"x\ty'\n\xff$HOME");
+        let mut script = OsString::from("printf %s ");
+        script.push(quote(arg));
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .output()
+            .unwrap();
+        assert_eq!(output.stdout, arg.as_bytes());
+    }
+
+    #[test]
+    fn join_keeps_operators() {
+        let args = ["git", "fetch", "&&", "git", "status"].map(OsString::from);
+        assert_eq!(join(&args), "git fetch && git status");
+    }
+}
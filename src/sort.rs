@@ -0,0 +1,116 @@
+use std::ffi::OsString;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::cli::SortBy;
+
+/// Wait for all paths of the walk and pass them on in a deterministic order.
+///
+/// Only the first `max_results` paths are passed on after sorting.
+pub fn sort(
+    rx: Receiver<PathBuf>,
+    by: SortBy,
+    reverse: bool,
+    max_results: Option<NonZeroUsize>,
+) -> Receiver<PathBuf> {
+    let mut paths = rx.into_iter().collect::<Vec<_>>();
+    sort_paths(&mut paths, by, reverse);
+    if let Some(max_results) = max_results {
+        paths.truncate(max_results.get());
+    }
+
+    let (tx, rx) = unbounded();
+    for path in paths {
+        tx.send(path).expect("receiver should still be alive");
+    }
+    rx
+}
+
+fn sort_paths(paths: &mut [PathBuf], by: SortBy, reverse: bool) {
+    match by {
+        SortBy::Path => paths.sort(),
+        SortBy::Name => paths.sort_by_cached_key(|path| (name(path), path.clone())),
+        SortBy::Depth => paths.sort_by_cached_key(|path| (depth(path), path.clone())),
+        // Paths without a known modification time are sorted last
+        SortBy::Mtime => paths.sort_by_cached_key(|path| {
+            let mtime = mtime(path);
+            (mtime.is_none(), mtime, path.clone())
+        }),
+    }
+    if reverse {
+        paths.reverse();
+    }
+}
+
+fn name(path: &Path) -> Option<OsString> {
+    path.file_name().map(ToOwned::to_owned)
+}
+
+fn depth(path: &Path) -> usize {
+    path.components().count()
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn case(by: SortBy, reverse: bool, input: &[&str], expected: &[&str]) {
+        let mut paths = input.iter().map(PathBuf::from).collect::<Vec<_>>();
+        sort_paths(&mut paths, by, reverse);
+        let expected = expected.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn by_path() {
+        case(
+            SortBy::Path,
+            false,
+            &["b/a", "a/b", "a"],
+            &["a", "a/b", "b/a"],
+        );
+    }
+
+    #[test]
+    fn by_path_reversed() {
+        case(
+            SortBy::Path,
+            true,
+            &["b/a", "a/b", "a"],
+            &["b/a", "a/b", "a"],
+        );
+    }
+
+    #[test]
+    fn by_name() {
+        case(
+            SortBy::Name,
+            false,
+            &["a/c", "b/a", "c/b"],
+            &["b/a", "c/b", "a/c"],
+        );
+    }
+
+    #[test]
+    fn by_name_ties_by_path() {
+        case(SortBy::Name, false, &["b/x", "a/x"], &["a/x", "b/x"]);
+    }
+
+    #[test]
+    fn by_depth() {
+        case(
+            SortBy::Depth,
+            false,
+            &["a/b/c", "z", "b/a"],
+            &["z", "b/a", "a/b/c"],
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver};
+
+use crate::cache::{Records, Tag};
+use crate::shell;
+
+const VERSION: &str = "project-below-state-1";
+
+/// States which were not written for this long are removed as they are unlikely to be resumed.
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The state is written at most this often while the commands run.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Completed,
+    Failed,
+    /// Found but the command did not finish
+    Pending,
+}
+
+impl Tag for Status {
+    const CHARS: &'static [(Self, char)] = &[
+        (Self::Completed, 'c'),
+        (Self::Failed, 'f'),
+        (Self::Pending, 'p'),
+    ];
+}
+
+/// Progress of the command in the projects below the `base_dir` so an interrupted run can be resumed.
+///
+/// The state is kept in the cache directory per `base_dir` and command.
+/// It is written from time to time while the commands run and removed once there is nothing left to resume
+/// or after `MAX_AGE` without a new run.
+pub struct State {
+    base_dir: PathBuf,
+    file: Option<PathBuf>,
+    command_line: String,
+    projects: Mutex<HashMap<PathBuf, Status>>,
+    /// When the state was written the last time. Locked while writing.
+    saved: Mutex<Instant>,
+    warned: AtomicBool,
+}
+
+/// The state of the previous run which decides which projects are skipped.
+pub struct Previous {
+    base_dir: PathBuf,
+    pub command_line: String,
+    projects: HashMap<PathBuf, Status>,
+    only_failed: bool,
+}
+
+impl State {
+    /// Do not record anything, for example for `--dry-run`.
+    pub fn disabled(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            file: None,
+            command_line: String::new(),
+            projects: Mutex::new(HashMap::new()),
+            saved: Mutex::new(Instant::now()),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Record a new run of the `command`. The projects skipped from the `previous` run keep their status.
+    ///
+    /// States of other runs older than `MAX_AGE` are removed.
+    pub fn new(base_dir: &Path, command: &[OsString], previous: Option<&Previous>) -> Self {
+        crate::cache::prune("state", MAX_AGE);
+        let projects = previous.map_or_else(HashMap::new, |previous| {
+            previous
+                .projects
+                .iter()
+                .filter(|(path, _)| previous.skips(path))
+                .map(|(path, status)| (path.clone(), *status))
+                .collect()
+        });
+        let command_line = std::env::args_os()
+            .map(|arg| shell::quote_display(&arg))
+            .collect::<Vec<_>>();
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            file: state_file(base_dir, command),
+            command_line: shell::join(&command_line).to_string_lossy().into_owned(),
+            projects: Mutex::new(projects),
+            saved: Mutex::new(Instant::now()),
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    pub fn pending(&self, path: &Path) {
+        self.set(path, Status::Pending);
+    }
+
+    pub fn finished(&self, path: &Path, success: bool) {
+        let status = if success {
+            Status::Completed
+        } else {
+            Status::Failed
+        };
+        self.set(path, status);
+        // Another thread is writing it already
+        let Ok(mut saved) = self.saved.try_lock() else {
+            return;
+        };
+        if saved.elapsed() >= SAVE_INTERVAL {
+            self.write();
+            *saved = Instant::now();
+        }
+    }
+
+    fn set(&self, path: &Path, status: Status) {
+        let key = path.strip_prefix(&self.base_dir).unwrap_or(path);
+        self.lock().insert(key.to_path_buf(), status);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, Status>> {
+        self.projects
+            .lock()
+            .expect("state lock should not be poisoned")
+    }
+
+    /// Write the final state once all commands finished.
+    ///
+    /// When the command completed in every project there is nothing to resume and the state is removed.
+    pub fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let _saved = self
+            .saved
+            .lock()
+            .expect("state lock should not be poisoned");
+        let completed = self
+            .lock()
+            .values()
+            .all(|status| *status == Status::Completed);
+        if !completed {
+            self.write();
+            return;
+        }
+        match std::fs::remove_file(file) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => self.warn(file, &err),
+            _ => {}
+        }
+    }
+
+    /// Needs to be called with the `saved` lock as parallel writes would use the same temporary file.
+    fn write(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let content = serialize(&self.command_line, &self.lock());
+        if let Err(err) = crate::cache::write(file, &content) {
+            self.warn(file, &err);
+        }
+    }
+
+    /// Only the first failure is reported to not repeat it for every write.
+    fn warn(&self, file: &Path, err: &std::io::Error) {
+        if !self.warned.swap(true, Ordering::Relaxed) {
+            eprintln!(
+                "project-below: failed to write the state to {}: {err}",
+                file.display()
+            );
+        }
+    }
+}
+
+impl Previous {
+    /// Load the state of the previous run of the `command` below the `base_dir`.
+    /// Without a previous run the `command_line` is empty.
+    ///
+    /// With `only_failed` all projects except the failed ones are skipped, otherwise only the completed ones.
+    pub fn load(base_dir: &Path, command: &[OsString], only_failed: bool) -> Self {
+        let (command_line, projects) = state_file(base_dir, command)
+            .and_then(|file| std::fs::read_to_string(file).ok())
+            .and_then(|content| parse(&content))
+            .unwrap_or_default();
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            command_line,
+            projects,
+            only_failed,
+        }
+    }
+
+    fn skips(&self, key: &Path) -> bool {
+        let status = self.projects.get(key).copied();
+        if self.only_failed {
+            status != Some(Status::Failed)
+        } else {
+            status == Some(Status::Completed)
+        }
+    }
+
+    /// Amount of projects of the previous run which are skipped.
+    pub fn skipped(&self) -> usize {
+        self.projects.keys().filter(|key| self.skips(key)).count()
+    }
+
+    /// Pass on the projects which are not skipped.
+    pub fn filter(self, rx: Receiver<PathBuf>) -> Receiver<PathBuf> {
+        let (tx, remaining) = unbounded();
+        std::thread::Builder::new()
+            .name("resume".to_owned())
+            .spawn(move || {
+                for path in rx {
+                    let key = path.strip_prefix(&self.base_dir).unwrap_or(&path);
+                    if !self.skips(key) && tx.send(path).is_err() {
+                        return;
+                    }
+                }
+            })
+            .expect("failed to spawn thread");
+        remaining
+    }
+}
+
+/// `$XDG_CACHE_HOME/project-below/state-<hash of the canonical base dir and the command>`
+fn state_file(base_dir: &Path, command: &[OsString]) -> Option<PathBuf> {
+    let base_dir = base_dir.canonicalize().ok()?;
+    let parts = std::iter::once(base_dir.as_os_str())
+        .chain(command.iter().map(OsString::as_os_str))
+        .collect::<Vec<_>>();
+    crate::cache::file("state", &parts)
+}
+
+/// The command line and then per project its status followed by its path.
+/// Paths which are not valid UTF-8 are not stored.
+fn serialize(command_line: &str, projects: &HashMap<PathBuf, Status>) -> String {
+    let mut records = Records::new(VERSION);
+    records.push(command_line);
+    for (path, status) in projects {
+        if let Some(path) = path.to_str() {
+            records.push_tagged(*status, path);
+        }
+    }
+    records.as_str().to_owned()
+}
+
+fn parse(content: &str) -> Option<(String, HashMap<PathBuf, Status>)> {
+    let mut records = crate::cache::records(content, VERSION)?;
+    let command_line = records.next()?.to_owned();
+    let mut projects = HashMap::new();
+    for record in records {
+        let (status, path) = crate::cache::tagged(record)?;
+        projects.insert(PathBuf::from(path), status);
+    }
+    Some((command_line, projects))
+}
+
+#[test]
+fn serialize_roundtrip() {
+    let mut projects = HashMap::new();
+    projects.insert(PathBuf::from("a"), Status::Completed);
+    projects.insert(PathBuf::from("b/c"), Status::Failed);
+    projects.insert(PathBuf::from(""), Status::Pending);
+    let (command_line, parsed) = parse(&serialize("cargo update", &projects)).unwrap();
+    assert_eq!(command_line, "cargo update");
+    assert_eq!(parsed, projects);
+}
+
+#[test]
+fn skips() {
+    let mut projects = HashMap::new();
+    projects.insert(PathBuf::from("completed"), Status::Completed);
+    projects.insert(PathBuf::from("failed"), Status::Failed);
+    projects.insert(PathBuf::from("pending"), Status::Pending);
+    let previous = |only_failed| Previous {
+        base_dir: PathBuf::new(),
+        command_line: String::new(),
+        projects: projects.clone(),
+        only_failed,
+    };
+
+    let resume = previous(false);
+    assert!(resume.skips(Path::new("completed")));
+    assert!(!resume.skips(Path::new("failed")));
+    assert!(!resume.skips(Path::new("pending")));
+    assert!(!resume.skips(Path::new("new")));
+
+    let rerun_failed = previous(true);
+    assert!(rerun_failed.skips(Path::new("completed")));
+    assert!(!rerun_failed.skips(Path::new("failed")));
+    assert!(rerun_failed.skips(Path::new("pending")));
+    assert!(rerun_failed.skips(Path::new("new")));
+}
+
+#[test]
+fn save_removes_completed() {
+    let file =
+        std::env::temp_dir().join(format!("project-below-state-test-{}", std::process::id()));
+    let state = State {
+        base_dir: PathBuf::from("/base"),
+        file: Some(file.clone()),
+        command_line: "true".to_owned(),
+        projects: Mutex::new(HashMap::new()),
+        saved: Mutex::new(Instant::now()),
+        warned: AtomicBool::new(false),
+    };
+    state.finished(Path::new("/base/a"), true);
+    state.pending(Path::new("/base/b"));
+    state.save();
+    let content = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(parse(&content).unwrap().1.len(), 2);
+
+    state.finished(Path::new("/base/b"), true);
+    state.save();
+    assert!(!file.exists());
+}
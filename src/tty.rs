@@ -0,0 +1,102 @@
+use std::sync::{Mutex, PoisonError};
+
+use rustix::process::{getpgrp, kill_process_group, waitid, Pid, Signal, WaitId, WaitIdOptions};
+use rustix::termios::{isatty, tcgetpgrp, tcsetpgrp};
+
+/// The terminal shared by commands running in the background in their own process groups.
+///
+/// A command trying to read from the terminal is stopped by the OS with `SIGTTIN` (or `SIGTTOU` when changing terminal settings, like for password prompts).
+/// It then gets the terminal as the foreground process group until it exits.
+/// Only one command at a time is in the foreground, the others keep running in the background.
+#[derive(Debug, Default)]
+pub struct Tty(Mutex<()>);
+
+impl Tty {
+    pub fn is_available() -> bool {
+        isatty(std::io::stdin())
+    }
+
+    /// Whether project-below is the foreground process group of the terminal.
+    pub fn is_foreground() -> bool {
+        tcgetpgrp(std::io::stdin()).is_ok_and(|foreground| foreground == getpgrp())
+    }
+
+    /// Handle the job control of the process group `pid` until it exits.
+    ///
+    /// The process is not reaped so the usual wait on the child still works.
+    pub fn job_control(&self, pid: u32) {
+        self.control(pid, false);
+    }
+
+    /// Give the process group `pid` the terminal right away and handle its job control until it exits.
+    ///
+    /// Only one command at a time can be in the foreground, so this is only for commands running one after another.
+    pub fn foreground(&self, pid: u32) {
+        self.control(pid, true);
+    }
+
+    fn control(&self, pid: u32, foreground: bool) {
+        let Some(pid) = i32::try_from(pid).ok().and_then(Pid::from_raw) else {
+            return;
+        };
+        // Taking the terminal back while in the background would stop project-below otherwise
+        let mut ttou = nix::sys::signal::SigSet::empty();
+        ttou.add(nix::sys::signal::Signal::SIGTTOU);
+        _ = ttou.thread_block();
+
+        if foreground && !self.run_in_foreground(pid) {
+            return;
+        }
+        while let Some(signal) = wait_for_stop(pid) {
+            if signal != Signal::TTIN && signal != Signal::TTOU {
+                // Stopped by something else like SIGSTOP, not our business
+                continue;
+            }
+            if !self.run_in_foreground(pid) {
+                return;
+            }
+        }
+    }
+
+    /// Continue the process group `pid` in the foreground until it stops or exits.
+    ///
+    /// Returns `false` once it exited.
+    fn run_in_foreground(&self, pid: Pid) -> bool {
+        let _foreground = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let own = getpgrp();
+        _ = tcsetpgrp(std::io::stdin(), pid);
+        _ = kill_process_group(pid, Signal::CONT);
+        let stopped = wait_for_stop(pid);
+        _ = tcsetpgrp(std::io::stdin(), own);
+        match stopped {
+            None => false,
+            // Like Ctrl+Z which only reaches the foreground. Keep it running in the background like `bg`.
+            Some(signal) if signal != Signal::TTIN && signal != Signal::TTOU => {
+                _ = kill_process_group(pid, Signal::CONT);
+                true
+            }
+            Some(_) => true,
+        }
+    }
+}
+
+/// Wait until the process stops and return the stopping signal or [`None`] when it exited.
+fn wait_for_stop(pid: Pid) -> Option<Signal> {
+    loop {
+        let options = WaitIdOptions::EXITED | WaitIdOptions::STOPPED | WaitIdOptions::NOWAIT;
+        match waitid(WaitId::Pid(pid), options) {
+            Ok(Some(status)) if status.stopped() => {
+                // Consume the stop so waiting again does not return it again
+                _ = waitid(
+                    WaitId::Pid(pid),
+                    WaitIdOptions::STOPPED | WaitIdOptions::NOHANG,
+                );
+                let signal = status.stopping_signal().and_then(Signal::from_named_raw);
+                return Some(signal.unwrap_or(Signal::STOP));
+            }
+            Err(rustix::io::Errno::INTR) => {}
+            // Exited or already reaped
+            _ => return None,
+        }
+    }
+}
@@ -1,19 +1,32 @@
 use std::num::NonZeroUsize;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::thread;
 
 use crossbeam_channel::{unbounded, Receiver};
 use ignore::WalkBuilder;
 
 use crate::check_dir_is_project::{check_dir_is_project, Pattern};
+use crate::index::Index;
+use crate::walk_error::WalkError;
 
+/// Walk the `base_dir` with `threads` or the default amount of threads.
+///
+/// The walk stops once `max_results` projects were found or `stop` is set.
+#[allow(clippy::too_many_arguments)]
 pub fn walk(
     base_dir: &Path,
     patterns: Vec<Pattern>,
     include_hidden: bool,
     recursive: bool,
-) -> Receiver<PathBuf> {
+    index: Index,
+    threads: Option<NonZeroUsize>,
+    max_results: Option<NonZeroUsize>,
+    stop: Arc<AtomicBool>,
+) -> (Receiver<PathBuf>, Receiver<WalkError>) {
     let (tx, rx) = unbounded();
+    let (err_tx, err_rx) = unbounded();
     let walk = WalkBuilder::new(base_dir)
         .hidden(!include_hidden)
         .filter_entry(|dir_entry| {
@@ -21,24 +34,52 @@ pub fn walk(
                 .file_type()
                 .is_some_and(|file_type| file_type.is_dir())
         })
-        .threads(default_num_threads().get())
+        .threads(threads.unwrap_or_else(default_num_threads).get())
         .build_parallel();
+    let index = Arc::new(index);
+    let found = Arc::new(AtomicUsize::new(0));
+    // Set when the walk ends before every directory was visited
+    let quit = Arc::new(AtomicBool::new(false));
+    let max_results = max_results.map_or(usize::MAX, NonZeroUsize::get);
     spawn("walker".to_owned(), move || {
         walk.run(|| {
             let patterns = patterns.clone();
+            let index = Arc::clone(&index);
+            let found = Arc::clone(&found);
+            let stop = Arc::clone(&stop);
+            let quit = Arc::clone(&quit);
             let tx = tx.clone();
+            let err_tx = err_tx.clone();
             Box::new(move |entry| {
+                if stop.load(Ordering::Relaxed) {
+                    quit.store(true, Ordering::Relaxed);
+                    return ignore::WalkState::Quit;
+                }
                 match entry {
                     Ok(dir_entry) => {
                         if let Some(err) = dir_entry.error() {
-                            eprintln!("Warning for path {}: {err}", dir_entry.path().display());
+                            _ = err_tx.send(WalkError::Warning {
+                                path: dir_entry.path().to_path_buf(),
+                                message: err.to_string(),
+                            });
                         }
                         if dir_entry.depth() == 0 {
                             return ignore::WalkState::Continue;
                         }
                         let path = dir_entry.into_path();
-                        if check_dir_is_project(&patterns, &path) {
-                            if tx.send(path).is_err() {
+                        let mut errors = Vec::new();
+                        let is_project =
+                            check_dir_is_project(&patterns, &path, &index, &mut errors);
+                        for (path, err) in errors {
+                            _ = err_tx.send(WalkError::Project { path, err });
+                        }
+                        if is_project {
+                            let before = found.fetch_add(1, Ordering::Relaxed);
+                            if before >= max_results
+                                || tx.send(path).is_err()
+                                || before + 1 == max_results
+                            {
+                                quit.store(true, Ordering::Relaxed);
                                 return ignore::WalkState::Quit;
                             }
                             if !recursive {
@@ -46,13 +87,19 @@ pub fn walk(
                             }
                         }
                     }
-                    Err(err) => eprintln!("Couldn't enter directory {err}"),
+                    Err(err) => _ = err_tx.send(WalkError::Unreadable(err)),
                 }
                 ignore::WalkState::Continue
             })
         });
+        drop(tx);
+        let index = Arc::into_inner(index).expect("all walker threads should be finished");
+        let complete = !quit.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed);
+        if let Err(err) = index.save(complete) {
+            _ = err_tx.send(WalkError::Index(err));
+        }
     });
-    rx
+    (rx, err_rx)
 }
 
 /// Get the default number of threads to use.
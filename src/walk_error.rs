@@ -0,0 +1,88 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+
+use crossbeam_channel::Receiver;
+
+#[derive(Debug)]
+pub enum WalkError {
+    /// Something like an unparsable ignore file. The walk itself continues normally.
+    Warning { path: PathBuf, message: String },
+    /// A directory could not be entered so projects below it might be missing.
+    Unreadable(ignore::Error),
+    /// A directory could not be read while checking a project for its patterns.
+    Project { path: PathBuf, err: std::io::Error },
+    /// The index of directory entries could not be saved. Only the next run is slower.
+    Index(std::io::Error),
+}
+
+impl WalkError {
+    pub const fn is_warning(&self) -> bool {
+        matches!(self, Self::Warning { .. } | Self::Index(_))
+    }
+}
+
+impl fmt::Display for WalkError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Warning { path, message } => {
+                write!(fmt, "Warning for path {}: {message}", path.display())
+            }
+            Self::Unreadable(err) => write!(fmt, "Couldn't enter directory {err}"),
+            Self::Project { path, err } => {
+                write!(fmt, "Couldn't check directory {}: {err}", path.display())
+            }
+            Self::Index(err) => write!(fmt, "Couldn't save the index of directory entries: {err}"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub warnings: usize,
+    pub unreadable: usize,
+}
+
+impl Summary {
+    /// The scan is incomplete when directories couldn't be read.
+    /// With `strict` warnings count as failures too.
+    pub const fn failed(&self, strict: bool) -> bool {
+        self.unreadable > 0 || (strict && self.warnings > 0)
+    }
+
+    pub fn print(&self) {
+        if self.warnings > 0 {
+            eprintln!(
+                "project-below: {} warnings during the directory walk",
+                self.warnings
+            );
+        }
+        if self.unreadable > 0 {
+            eprintln!(
+                "project-below: {} directories could not be read, the results might be incomplete",
+                self.unreadable
+            );
+        }
+    }
+}
+
+/// Print the errors of the walk while it runs and count them.
+pub fn report(errors: Receiver<WalkError>, print: bool) -> JoinHandle<Summary> {
+    thread::Builder::new()
+        .name("walk-errors".to_owned())
+        .spawn(move || {
+            let mut summary = Summary::default();
+            for err in errors {
+                if print {
+                    eprintln!("{err}");
+                }
+                if err.is_warning() {
+                    summary.warnings += 1;
+                } else {
+                    summary.unreadable += 1;
+                }
+            }
+            summary
+        })
+        .expect("failed to spawn thread")
+}
@@ -0,0 +1,242 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+use ignore::gitignore::Gitignore;
+use notify::{RecursiveMode, Watcher};
+
+use crate::check_dir_is_project::{check_dir_is_project, Pattern};
+use crate::index::Index;
+
+/// Changes are collected until nothing changed for this long.
+/// Saving multiple files or a `git checkout` results in a single rerun this way.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Changes this shortly after the command of a project finished are assumed to be caused by it.
+const GRACE: Duration = Duration::from_millis(100);
+
+/// A change and when it was reported.
+type Event = (Instant, notify::Result<notify::Event>);
+
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    Queued,
+    Running,
+    Finished(Instant),
+}
+
+/// Which projects wait for or run their command.
+///
+/// Changes to a project while its command is queued or running do not queue it again.
+/// This way a project never runs concurrently with itself and commands writing into their own project do not trigger themselves over and over.
+#[derive(Debug, Default)]
+pub struct Activity(Mutex<HashMap<PathBuf, Phase>>);
+
+impl Activity {
+    pub fn started(&self, project: &Path) {
+        self.set(project, Phase::Running);
+    }
+
+    pub fn finished(&self, project: &Path) {
+        self.set(project, Phase::Finished(Instant::now()));
+    }
+
+    fn set(&self, project: &Path, phase: Phase) {
+        self.0
+            .lock()
+            .expect("activity lock should not be poisoned")
+            .insert(project.to_path_buf(), phase);
+    }
+
+    /// Whether a change `at` that time happened while the project was queued, running or just finished.
+    fn is_busy(&self, project: &Path, at: Instant) -> bool {
+        match self
+            .0
+            .lock()
+            .expect("activity lock should not be poisoned")
+            .get(project)
+        {
+            None => false,
+            Some(Phase::Queued | Phase::Running) => true,
+            Some(Phase::Finished(finished)) => at <= *finished + GRACE,
+        }
+    }
+}
+
+/// Pass on the `initial` projects and keep watching `base_dir` afterwards.
+///
+/// Projects appearing later on are passed on too.
+/// With `rerun_changed` projects are passed on again when files inside them changed, unless the change happened while the project was busy according to the `activity`.
+/// Projects which no longer match the patterns are forgotten instead.
+pub fn watch(
+    base_dir: PathBuf,
+    patterns: Vec<Pattern>,
+    include_hidden: bool,
+    recursive: bool,
+    rerun_changed: bool,
+    initial: Receiver<PathBuf>,
+    activity: Arc<Activity>,
+) -> notify::Result<Receiver<PathBuf>> {
+    let (tx, rx) = unbounded();
+    let (event_tx, event_rx) = unbounded();
+    // Start watching before the initial projects are passed on to not miss changes in between
+    let mut watcher = notify::recommended_watcher(move |event| {
+        _ = event_tx.send((Instant::now(), event));
+    })?;
+    watcher.watch(&base_dir, RecursiveMode::Recursive)?;
+
+    std::thread::Builder::new()
+        .name("watcher".to_owned())
+        .spawn(move || {
+            // Keep the watcher alive as long as the thread runs
+            let _watcher = watcher;
+            let index = Index::disabled(&base_dir);
+            // The watcher reports absolute paths while the walk reports them based on the base_dir
+            let absolute_base = std::env::current_dir()
+                .map_or_else(|_| base_dir.clone(), |pwd| pwd.join(&base_dir));
+            let mut known = HashSet::new();
+            for path in initial {
+                known.insert(path.clone());
+                activity.set(&path, Phase::Queued);
+                if tx.send(path).is_err() {
+                    return;
+                }
+            }
+
+            while let Some(changed) = debounced(&event_rx) {
+                known.retain(|project| project.is_dir());
+                let mut found = BTreeSet::new();
+                let mut checked = HashSet::new();
+                let changed = changed.iter().filter_map(|(path, at)| {
+                    let relative = path.strip_prefix(&absolute_base).ok()?;
+                    Some((base_dir.join(relative), *at))
+                });
+                for (path, at) in changed {
+                    if is_ignored(&base_dir, &path, include_hidden) {
+                        continue;
+                    }
+                    if rerun_changed {
+                        let project = known
+                            .iter()
+                            .filter(|project| path.starts_with(project))
+                            .max_by_key(|project| project.components().count())
+                            .cloned();
+                        if let Some(project) = project {
+                            let mut errors = Vec::new();
+                            if !check_dir_is_project(&patterns, &project, &index, &mut errors) {
+                                // The change removed what made it a project
+                                known.remove(&project);
+                            } else if !activity.is_busy(&project, at) {
+                                found.insert(project);
+                            }
+                        }
+                    }
+
+                    let candidates = path
+                        .ancestors()
+                        .skip(usize::from(!path.is_dir()))
+                        .take_while(|dir| *dir != base_dir && dir.starts_with(&base_dir));
+                    for dir in candidates {
+                        if !checked.insert(dir.to_path_buf()) || known.contains(dir) {
+                            continue;
+                        }
+                        let is_below_project =
+                            dir.ancestors().skip(1).any(|parent| known.contains(parent));
+                        if is_below_project && !recursive {
+                            continue;
+                        }
+                        // Errors are expected for directories removed in the meantime
+                        let mut errors = Vec::new();
+                        if check_dir_is_project(&patterns, dir, &index, &mut errors) {
+                            known.insert(dir.to_path_buf());
+                            found.insert(dir.to_path_buf());
+                        }
+                    }
+                }
+                for path in found {
+                    activity.set(&path, Phase::Queued);
+                    if tx.send(path).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+        .expect("failed to spawn thread");
+    Ok(rx)
+}
+
+/// Wait for the next change and collect all the changed paths until nothing changed for `DEBOUNCE`.
+/// Every path is returned once with the time of its latest change.
+///
+/// Returns `None` when the watcher stopped.
+fn debounced(events: &Receiver<Event>) -> Option<BTreeMap<PathBuf, Instant>> {
+    let mut changed = BTreeMap::new();
+    let mut add = |(at, event): Event| match event {
+        Ok(event) if !event.kind.is_access() => {
+            changed.extend(event.paths.into_iter().map(|path| (path, at)));
+        }
+        Ok(_) => {}
+        Err(err) => eprintln!("project-below: watch error: {err}"),
+    };
+    add(events.recv().ok()?);
+    loop {
+        match events.recv_timeout(DEBOUNCE) {
+            Ok(event) => add(event),
+            Err(RecvTimeoutError::Timeout) => return Some(changed),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+}
+
+/// Approximates the ignore rules of the directory walk for a single path.
+///
+/// Hidden paths and `.gitignore` / `.ignore` files of every directory between `base_dir` and `path` are respected.
+/// `.git` directories are always ignored as they change on nearly every `git` command.
+fn is_ignored(base_dir: &Path, path: &Path, include_hidden: bool) -> bool {
+    let Ok(relative) = path.strip_prefix(base_dir) else {
+        return true;
+    };
+    let is_hidden = relative.components().any(|component| {
+        let name = component.as_os_str();
+        name == ".git" || (!include_hidden && name.to_string_lossy().starts_with('.'))
+    });
+    if is_hidden {
+        return true;
+    }
+
+    let is_dir = path.is_dir();
+    // The deepest ignore file wins as it might whitelist something ignored further up
+    for dir in path.ancestors().skip(1) {
+        for file in [".ignore", ".gitignore"] {
+            let (gitignore, _) = Gitignore::new(dir.join(file));
+            let matched = gitignore.matched_path_or_any_parents(path, is_dir);
+            if matched.is_ignore() {
+                return true;
+            }
+            if matched.is_whitelist() {
+                return false;
+            }
+        }
+        if dir == base_dir {
+            break;
+        }
+    }
+    false
+}
+
+#[test]
+fn activity_is_busy() {
+    let activity = Activity::default();
+    let project = Path::new("project");
+    let before = Instant::now();
+    assert!(!activity.is_busy(project, before));
+    activity.set(project, Phase::Queued);
+    assert!(activity.is_busy(project, before));
+    activity.started(project);
+    assert!(activity.is_busy(project, Instant::now()));
+    activity.finished(project);
+    assert!(activity.is_busy(project, Instant::now()));
+    assert!(!activity.is_busy(project, Instant::now() + GRACE * 2));
+}